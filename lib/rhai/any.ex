@@ -4,5 +4,21 @@ defmodule Rhai.Any do
   """
 
   @type t() ::
-          number() | boolean() | String.t() | nil | [t()] | %{String.t() => t()}
+          number()
+          | boolean()
+          | String.t()
+          | binary()
+          | {:blob, binary()}
+          | nil
+          | [t()]
+          | tuple()
+          | keyword(t())
+          | %{(String.t() | atom() | integer()) => t()}
+          | Rhai.FnPtr.t()
+          | DateTime.t()
+          | NaiveDateTime.t()
+          | fun()
+          | pid()
+          | reference()
+          | port()
 end
@@ -0,0 +1,15 @@
+defmodule Rhai.Application do
+  @moduledoc false
+
+  use Application
+
+  @impl true
+  def start(_type, _args) do
+    children = [
+      {Task.Supervisor, name: Rhai.TaskSupervisor},
+      Rhai.Dispatcher
+    ]
+
+    Supervisor.start_link(children, strategy: :one_for_one, name: Rhai.Supervisor)
+  end
+end
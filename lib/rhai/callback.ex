@@ -0,0 +1,39 @@
+defmodule Rhai.Callback do
+  @moduledoc """
+  Reply to calls made by Rhai scripts into Elixir processes.
+
+  A process handling a function registered with `Rhai.Engine.register_fn/5` receives
+  `{:rhai_call, callback, name, args}` messages, and a process serving a `Rhai.ProcessModuleResolver`
+  receives `{:rhai_import, callback, path}` messages. The script is blocked until `reply/2` is called
+  with the `callback`, so each message must be replied to exactly once.
+
+  ## Example
+
+      def handle_info({:rhai_call, callback, "lookup", [key]}, state) do
+        Rhai.Callback.reply(callback, {:ok, Map.get(state, key)})
+
+        {:noreply, state}
+      end
+  """
+
+  @type t :: reference()
+
+  @doc """
+  Reply to a call with `{:ok, value}` or `{:error, reason}`.
+
+  An error reply makes the script fail with a `:runtime` error.
+  Imports are replied to with the source of the module or its compiled `Rhai.AST`.
+  """
+  @spec reply(t(), {:ok, Rhai.Any.t() | Rhai.AST.t()} | {:error, term()}) :: :ok
+  def reply(callback, {:ok, %Rhai.AST{resource: resource}}) do
+    Rhai.Native.callback_reply(callback, {:ok, resource})
+
+    :ok
+  end
+
+  def reply(callback, result) do
+    Rhai.Native.callback_reply(callback, result)
+
+    :ok
+  end
+end
@@ -0,0 +1,69 @@
+defmodule Rhai.CancelToken do
+  @moduledoc """
+  Token used to cancel a running evaluation from another process.
+
+  Pass the token to any evaluation function with the `:cancel_token` option and call `cancel/2` from another process.
+  The script stops at the next operation with a `{:terminated, message, reason}` error, where `reason` is the term
+  given to `cancel/2`.
+
+  A token can be shared by several evaluations, and once cancelled it stays cancelled.
+
+  ## Example
+
+      token = Rhai.CancelToken.new()
+
+      task = Task.async(fn -> Rhai.Engine.eval(engine, "loop {}", cancel_token: token) end)
+      Rhai.CancelToken.cancel(token, :shutdown)
+
+      {:error, {:terminated, _message, :shutdown}} = Task.await(task)
+  """
+
+  defstruct [
+    # The actual NIF Resource.
+    resource: nil,
+    # Normally the compiler will happily do stuff like inlining the
+    # resource in attributes. This will convert the resource into an
+    # empty binary with no warning. This will make that harder to
+    # accidentaly do.
+    # It also serves as a handy way to tell file handles apart.
+    reference: nil
+  ]
+
+  @type t :: %__MODULE__{}
+
+  @doc """
+  Create a new cancellation token.
+  """
+  @spec new :: t()
+  def new do
+    wrap_resource(Rhai.Native.cancel_token_new())
+  end
+
+  @doc """
+  Cancel all the evaluations using the token.
+
+  The `reason` is returned in the `:terminated` error. Only the reason of the first cancellation is kept.
+  """
+  @spec cancel(t(), term()) :: t()
+  def cancel(%__MODULE__{resource: resource} = cancel_token, reason \\ :cancelled) do
+    Rhai.Native.cancel_token_cancel(resource, reason)
+
+    cancel_token
+  end
+
+  @doc """
+  Return true if the token was cancelled.
+  """
+  @spec cancelled?(t()) :: boolean()
+  def cancelled?(%__MODULE__{resource: resource}) do
+    Rhai.Native.cancel_token_cancelled(resource)
+  end
+
+  @doc false
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
@@ -0,0 +1,32 @@
+defmodule Rhai.Dispatcher do
+  @moduledoc false
+
+  # Runs the Elixir funs called by scripts.
+  #
+  # Scripts send `{:rhai_call, callback, fun, args}` messages, and every call runs in its own task
+  # so that slow or crashing funs do not affect other calls.
+
+  use GenServer
+
+  def start_link(_opts) do
+    GenServer.start_link(__MODULE__, nil, name: __MODULE__)
+  end
+
+  @impl true
+  def init(nil), do: {:ok, nil}
+
+  @impl true
+  def handle_info({:rhai_call, callback, fun, args}, state) do
+    Task.Supervisor.start_child(Rhai.TaskSupervisor, fn ->
+      Rhai.Callback.reply(callback, apply_fun(fun, args))
+    end)
+
+    {:noreply, state}
+  end
+
+  defp apply_fun(fun, args) do
+    {:ok, apply(fun, args)}
+  catch
+    kind, reason -> {:error, Exception.format_banner(kind, reason, __STACKTRACE__)}
+  end
+end
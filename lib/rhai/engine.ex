@@ -1,6 +1,62 @@
 defmodule Rhai.Engine do
   @moduledoc """
   Rhai main scripting engine.
+
+  ## Concurrency
+
+  Any number of processes can evaluate scripts with the same engine and the same AST at the same time.
+  A scope, however, can only be used by one evaluation at a time.
+
+  Configuring an engine (setting limits, registering modules or functions...) while it is evaluating a script fails
+  with a `{:busy, message}` error, which is raised by the functions returning the engine. The same applies to
+  updating an AST while it is being evaluated, and to using a scope while an evaluation is using it.
+
+  ## Elixir functions
+
+  Elixir functions passed to scripts, as arguments or scope values, are converted into function pointers.
+  Scripts call them with `f.call(x)` or `call(f, x)`, or pass them to functions such as `map` and `filter`,
+  and get them back as Elixir functions when returning them.
+
+  The functions run in processes of the `rhai_rustler` application, and their return value is converted
+  back into a Rhai value. A function that raises makes the script fail with a `:runtime` error. Functions
+  may run for as long as the evaluation: waiting for a function stops at the `:timeout` of the evaluation,
+  if any, or when it is cancelled.
+
+  ## Evaluation options
+
+  The `eval*`, `run*` and `call_fn*` functions accept the following options:
+
+    * `:cancel_token` - a `Rhai.CancelToken` used to stop the evaluation from another process.
+      A cancelled evaluation returns a `{:terminated, message, reason}` error.
+
+    * `:timeout` - the maximum wall-clock time in milliseconds the evaluation may run for.
+      Unlike `set_max_operations/2`, it also accounts for the time spent in slow registered or dylib functions.
+      The deadline is checked between operations, and an evaluation running past it returns a `{:timeout, message}` error.
+
+    * `:capture_output` - when `true`, the output of `print` and `debug` is collected instead of being sent
+      to the output set with `set_output/2`, and the function returns `{result, output}`, where `result` is
+      the usual return value and `output` is a list of `t:output/0` in the order it was written.
+      The asynchronous functions reply with `{ref, {result, output}}` instead. Defaults to `false`.
+
+    * `:detailed_errors` - when `true`, errors are returned as `t:Rhai.Error.details/0` maps, with the
+      position, the source and the call stack of the error, instead of tuples. Defaults to `false`.
+
+    * `:map_keys` - the keys of the maps returned by the evaluation and passed to Elixir functions,
+      `:strings` (the default) or `:atoms`. With `:atoms`, maps passed to the evaluation are returned
+      with their keys as they were passed, so that `%{id: 1}` is returned with an atom key and
+      `%{1 => "a"}` with an integer key. The keys of object maps created by scripts are strings.
+      The kinds of the keys are recorded in the tag of the object maps, so maps whose tag is set
+      by scripts with `set_tag` may be returned with string keys.
+
+    * `:tuples` - how the tuples passed to the evaluation, as arguments or returned by Elixir
+      functions, are converted: `:arrays` (the default) or `:tagged`. See `t:Rhai.Scope.tuples/0`.
+
+  ## Compilation options
+
+  The `compile*` functions accept the following options:
+
+    * `:detailed_errors` - when `true`, errors are returned as `t:Rhai.Error.details/0` maps, with the
+      kind and the position of parse errors, instead of tuples. Defaults to `false`.
   """
 
   alias Rhai.{AST, Scope}
@@ -18,8 +74,36 @@ defmodule Rhai.Engine do
 
   @type t :: %__MODULE__{}
 
+  @typedoc """
+  Text written by a script with `print` or `debug`.
+
+  Debug output includes the source of the script, if any, and the `{line, column}` position of the call.
+  """
+  @type output ::
+          {:print, String.t()}
+          | {:debug, String.t(), String.t() | nil, {pos_integer(), pos_integer() | nil} | nil}
+
+  @typedoc """
+  Return value of the evaluation functions, along with the output when `:capture_output` is set.
+  """
+  @type eval_result(result) :: result | {result, [output()]}
+
+  @typedoc """
+  Module resolution service, see `set_module_resolvers/2`.
+  """
+  @type module_resolver ::
+          :file
+          | {:file, keyword()}
+          | :dylib
+          | Rhai.StaticModuleResolver.t()
+          | Rhai.ProcessModuleResolver.t()
+
   @doc """
   Create a new Engine
+
+  Along with the standard Rhai packages, the engine includes the `:date_time` package of wall-clock
+  time functions, the `:collections` package of the functions of Elixir tuples and the `:opaque`
+  package comparing and printing Elixir pids, references and ports.
   """
   @spec new :: t()
   def new do
@@ -37,11 +121,60 @@ defmodule Rhai.Engine do
   @doc """
   Set the module resolution services used by the Engine.
 
-  This library supports `:file` and `:dylib` module resolvers.
+  This library supports `:file` and `:dylib` module resolvers, `Rhai.StaticModuleResolver`
+  for modules compiled from sources registered by Elixir, and `Rhai.ProcessModuleResolver`
+  for modules requested from an Elixir process.
+  Resolvers are tried in order until one of them finds the module.
+
+  ## File resolver options
+
+  The file resolver can be configured with `{:file, opts}`:
+
+    * `:base_path` - the directory relative imports are resolved from. Defaults to the directory of the
+      importing script, or the current working directory.
+    * `:extension` - the extension of the module files, defaults to `"rhai"`.
+    * `:cache` - whether loaded modules are cached, defaults to `true`.
+    * `:sandbox` - when `true`, imports of files outside of the base path, for example through `../`,
+      absolute paths or symbolic links, fail with a `:module_not_found` error. The base path defaults to
+      the current working directory. Defaults to `false`.
+
+  ## Options
+
+  The module paths scripts can import can be restricted, whatever the resolvers, with glob patterns
+  where `*` and `?` match any characters but `/` and `**` matches any characters:
+
+    * `:allow` - the patterns of the paths that can be imported. Defaults to allowing any path.
+    * `:deny` - the patterns of the paths that cannot be imported, even if they are allowed.
+    * `:forbidden_error` - the error returned when importing a path that is not allowed,
+      `:module_forbidden` (the default) or `:module_not_found`.
+
+  For example, `allow: ["std/*", "tenant/**"], deny: ["tenant/internal/**"]`.
+
+  When the paths are restricted, paths that are absolute or contain backslashes, empty, `.` or `..`
+  segments, such as `"tenant/../secret"`, are never allowed.
   """
-  @spec set_module_resolvers(t(), [:file | :dylib]) :: t()
-  def set_module_resolvers(%__MODULE__{resource: resource} = engine, module_resolvers) do
-    Rhai.Native.engine_set_module_resolvers(resource, module_resolvers)
+  @spec set_module_resolvers(t(), [module_resolver()], keyword()) :: t()
+  def set_module_resolvers(
+        %__MODULE__{resource: resource} = engine,
+        module_resolvers,
+        opts \\ []
+      ) do
+    module_resolvers =
+      Enum.map(module_resolvers, fn
+        :file -> {:file, file_resolver_options([])}
+        {:file, opts} -> {:file, file_resolver_options(opts)}
+        %Rhai.StaticModuleResolver{resource: resolver_resource} -> {:static, resolver_resource}
+        %Rhai.ProcessModuleResolver{resource: resolver_resource} -> {:process, resolver_resource}
+        module_resolver -> module_resolver
+      end)
+
+    access = %{
+      allow: Keyword.get(opts, :allow),
+      deny: Keyword.get(opts, :deny, []),
+      forbidden_error: Keyword.get(opts, :forbidden_error, :module_forbidden)
+    }
+
+    Rhai.Native.engine_set_module_resolvers(resource, module_resolvers, access)
 
     engine
   end
@@ -56,7 +189,7 @@ defmodule Rhai.Engine do
   Returns an error if the module cannot be loaded. 
   """
   @spec register_global_module(t(), String.t()) ::
-          {:ok, t()} | {:error, {:runtime, String.t()}}
+          {:ok, t()} | {:error, {:runtime, String.t(), String.t()} | {:busy, String.t()}}
   def register_global_module(%__MODULE__{resource: resource} = engine, path) do
     with {:ok, _} <- Rhai.Native.engine_register_global_module(resource, path) do
       {:ok, engine}
@@ -78,7 +211,10 @@ defmodule Rhai.Engine do
       {:ok, _} ->
         engine
 
-      {:error, {:runtime, message}} ->
+      {:error, {:runtime, message, _}} ->
+        raise message
+
+      {:error, {_, message}} ->
         raise message
     end
   end
@@ -89,7 +225,7 @@ defmodule Rhai.Engine do
   Returns an error if the module cannot be loaded. 
   """
   @spec register_static_module(t(), String.t(), String.t()) ::
-          {:ok, t()} | {:error, {:runtime, String.t()}}
+          {:ok, t()} | {:error, {:runtime, String.t(), String.t()} | {:busy, String.t()}}
   def register_static_module(%__MODULE__{resource: resource} = engine, namespace, path) do
     with {:ok, _} <-
            Rhai.Native.engine_register_static_module(resource, namespace, path) do
@@ -108,11 +244,96 @@ defmodule Rhai.Engine do
       {:ok, _} ->
         engine
 
-      {:error, {:runtime, message}} ->
+      {:error, {:runtime, message, _}} ->
+        raise message
+
+      {:error, {_, message}} ->
         raise message
     end
   end
 
+  @doc """
+  Evaluate a script into a Module and register it into the global namespace of Engine.
+
+  The script is either a compiled `Rhai.AST` or its source. Its functions become available to every
+  script evaluated by the engine without namespace qualifications, so shared helper libraries written
+  in Rhai do not need to be imported. Variables are ignored.
+
+  Returns an error if the script fails to compile or evaluate.
+  See the [evaluation options](#module-evaluation-options).
+  """
+  @spec register_global_script_module(t(), AST.t() | String.t(), keyword()) ::
+          eval_result({:ok, t()} | {:error, Rhai.Error.t()})
+  def register_global_script_module(%__MODULE__{} = engine, script, opts \\ []) do
+    register_script_module(engine, nil, script, opts)
+  end
+
+  @doc """
+  Evaluate a script into a Module and register it into the namespace of Engine.
+
+  The script is either a compiled `Rhai.AST` or its source. Its functions and exported variables
+  are available to every script evaluated by the engine under `namespace::`, without importing it.
+
+  Returns an error if the script fails to compile or evaluate.
+  See the [evaluation options](#module-evaluation-options).
+  """
+  @spec register_static_script_module(t(), String.t(), AST.t() | String.t(), keyword()) ::
+          eval_result({:ok, t()} | {:error, Rhai.Error.t()})
+  def register_static_script_module(%__MODULE__{} = engine, namespace, script, opts \\ []) do
+    register_script_module(engine, namespace, script, opts)
+  end
+
+  defp register_script_module(%__MODULE__{resource: resource} = engine, ns, script, opts) do
+    script =
+      case script do
+        %AST{resource: ast_resource} -> {:ast, ast_resource}
+        source when is_binary(source) -> {:source, source}
+      end
+
+    evaluate(opts, fn options ->
+      with {:ok, _} <-
+             Rhai.Native.engine_register_script_module(resource, ns, script, options) do
+        {:ok, engine}
+      end
+    end)
+  end
+
+  @doc """
+  Register constants into the namespace of Engine.
+
+  Unlike the variables of a `Rhai.Scope`, the constants are visible everywhere, including inside
+  script functions, as `namespace::name`. Keys can be strings or atoms, and values are converted like
+  any other value passed to a script.
+
+  Returns an error if a value cannot be converted into a Rhai value.
+
+  ## Options
+
+    * `:tuples` - how tuples are converted, `:arrays` (the default) or `:tagged`.
+      See `t:Rhai.Scope.tuples/0`.
+
+  ## Example
+
+      {:ok, engine} = Rhai.Engine.register_constants_module(engine, "config", %{max_retries: 3})
+      {:ok, 6} = Rhai.Engine.eval(engine, "fn retries() { config::max_retries * 2 } retries()")
+  """
+  @spec register_constants_module(t(), String.t(), map() | keyword(), keyword()) ::
+          {:ok, t()} | {:error, {:conversion | :busy, String.t()}}
+  def register_constants_module(
+        %__MODULE__{resource: resource} = engine,
+        namespace,
+        constants,
+        opts \\ []
+      ) do
+    constants = Map.new(constants, fn {name, value} -> {to_string(name), value} end)
+    tuples = Keyword.get(opts, :tuples, :arrays)
+
+    with {:ok, _} <-
+           Rhai.Native.engine_register_constants_module(resource, namespace, constants, tuples) do
+      {:ok, engine}
+    end
+  end
+
   @doc """
   Register a custom operator with a precedence into the language.
 
@@ -120,9 +341,14 @@ defmodule Rhai.Engine do
   The precedence cannot be zero.
   """
   @spec register_custom_operator(t(), String.t(), non_neg_integer()) ::
-          {:ok, t()} | {:error, {:custom_operator, String.t()}}
+          {:ok, t()} | {:error, {:custom_operator | :busy, String.t()}}
   def register_custom_operator(%__MODULE__{resource: resource} = engine, operator, precedence) do
-    with {:ok, _} <- Rhai.Native.engine_register_custom_operator(resource, operator, precedence) do
+    with {:ok, _} <-
+           Rhai.Native.engine_register_custom_operator(
+             resource,
+             operator,
+             precedence
+           ) do
       {:ok, engine}
     end
   end
@@ -141,7 +367,7 @@ defmodule Rhai.Engine do
       {:ok, _} ->
         engine
 
-      {:error, {:custom_operator, message}} ->
+      {:error, {_, message}} ->
         raise message
     end
   end
@@ -156,12 +382,67 @@ defmodule Rhai.Engine do
     engine
   end
 
+  @doc """
+  Register a function with a fixed number of parameters whose calls are forwarded to an Elixir process.
+
+  Every time a script calls the function, `pid` receives a `{:rhai_call, callback, name, args}` message
+  and must answer it with `Rhai.Callback.reply/2`. The reply value is converted back into a Rhai value.
+
+  The script fails with a `:runtime` error if the reply is an error, if the process is not alive
+  or if it does not reply in time.
+
+  ## Options
+
+    * `:timeout` - the maximum time in milliseconds to wait for a reply, defaults to `5000`.
+  """
+  @spec register_fn(t(), String.t(), non_neg_integer(), pid(), keyword()) :: t()
+  def register_fn(%__MODULE__{resource: resource} = engine, name, arity, pid, opts \\ []) do
+    timeout = Keyword.get(opts, :timeout, 5000)
+
+    Rhai.Native.engine_register_fn(resource, name, arity, pid, timeout)
+
+    engine
+  end
+
+  @doc """
+  Send the output of `print` and `debug` to a process instead of stdout.
+
+  The process receives a `{:rhai_output, output}` message for every call, where `output` is a `t:output/0`.
+  Output sent to a process that is not alive is dropped. Pass `nil` to print to stdout again.
+
+  ## Example
+
+  Forwarding the output of scripts to `Logger`:
+
+      def handle_info({:rhai_output, {:print, text}}, state) do
+        Logger.info(text)
+
+        {:noreply, state}
+      end
+
+      def handle_info({:rhai_output, {:debug, text, source, position}}, state) do
+        Logger.debug(text, rhai_source: source, rhai_position: position)
+
+        {:noreply, state}
+      end
+  """
+  @spec set_output(t(), pid() | nil) :: t()
+  def set_output(%__MODULE__{resource: resource} = engine, pid) do
+    Rhai.Native.engine_set_output(resource, pid)
+
+    engine
+  end
+
   @doc """
   Compile a string into an AST, which can be used later for evaluation.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile(t(), String.t()) :: {:ok, AST.t()} | {:error, Rhai.Error.t()}
-  def compile(%__MODULE__{resource: resource}, script) do
-    with {:ok, ast_resource} <- Rhai.Native.engine_compile(resource, script) do
+  @spec compile(t(), String.t(), keyword()) :: {:ok, AST.t()} | {:error, Rhai.Error.t()}
+  def compile(%__MODULE__{resource: resource}, script, opts \\ []) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
+    with {:ok, ast_resource} <- Rhai.Native.engine_compile(resource, script, detailed_errors) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
@@ -172,69 +453,108 @@ defmodule Rhai.Engine do
   Constants Propagation:
   If not `optimization_level = :none`, constants defined within the scope are propagated throughout the script including functions.
   This allows functions to be optimized based on dynamic global constants.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_with_scope(t(), Scope.t(), String.t()) ::
+  @spec compile_with_scope(t(), Scope.t(), String.t(), keyword()) ::
           {:ok, AST.t()} | {:error, Rhai.Error.t()}
   def compile_with_scope(
         %__MODULE__{resource: resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
     with {:ok, ast_resource} <-
-           Rhai.Native.engine_compile_with_scope(resource, scope_resource, script) do
+           Rhai.Native.engine_compile_with_scope(
+             resource,
+             scope_resource,
+             script,
+             detailed_errors
+           ) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
 
   @doc """
   Compile a string containing an expression into an AST, which can be used later for evaluation.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_expression(t(), String.t()) :: {:ok, AST.t()} | {:error, Rhai.Error.t()}
-  def compile_expression(%__MODULE__{resource: resource}, script) do
-    with {:ok, ast_resource} <- Rhai.Native.engine_compile_expression(resource, script) do
+  @spec compile_expression(t(), String.t(), keyword()) :: {:ok, AST.t()} | {:error, Rhai.Error.t()}
+  def compile_expression(%__MODULE__{resource: resource}, script, opts \\ []) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
+    with {:ok, ast_resource} <-
+           Rhai.Native.engine_compile_expression(resource, script, detailed_errors) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
 
   @doc """
   Compile a string containing an expression into an AST using own scope, which can be used later for evaluation.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_expression_with_scope(t(), Scope.t(), String.t()) ::
+  @spec compile_expression_with_scope(t(), Scope.t(), String.t(), keyword()) ::
           {:ok, AST.t()} | {:error, Rhai.Error.t()}
   def compile_expression_with_scope(
         %__MODULE__{resource: resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
     with {:ok, ast_resource} <-
-           Rhai.Native.engine_compile_expression_with_scope(resource, scope_resource, script) do
+           Rhai.Native.engine_compile_expression_with_scope(
+             resource,
+             scope_resource,
+             script,
+             detailed_errors
+           ) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
 
   @doc """
   Compile a script file into an AST, which can be used later for evaluation.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_file(t(), String.t()) :: {:ok, AST.t()} | {:error, Rhai.Error.t()}
-  def compile_file(%__MODULE__{resource: resource}, path) do
+  @spec compile_file(t(), String.t(), keyword()) :: {:ok, AST.t()} | {:error, Rhai.Error.t()}
+  def compile_file(%__MODULE__{resource: resource}, path, opts \\ []) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
     with {:ok, ast_resource} <-
-           Rhai.Native.engine_compile_file(resource, path) do
+           Rhai.Native.engine_compile_file(resource, path, detailed_errors) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
 
   @doc """
   Compile a script file into an AST using own scope, which can be used later for evaluation.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_file_with_scope(t(), Scope.t(), String.t()) ::
+  @spec compile_file_with_scope(t(), Scope.t(), String.t(), keyword()) ::
           {:ok, AST.t()} | {:error, Rhai.Error.t()}
   def compile_file_with_scope(
         %__MODULE__{resource: resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
     with {:ok, ast_resource} <-
-           Rhai.Native.engine_compile_file_with_scope(resource, scope_resource, script) do
+           Rhai.Native.engine_compile_file_with_scope(
+             resource,
+             scope_resource,
+             script,
+             detailed_errors
+           ) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
@@ -242,16 +562,26 @@ defmodule Rhai.Engine do
   @doc """
   Compile a string into an AST using own scope, which can be used later for evaluation, embedding all imported modules.
   Modules referred by import statements containing literal string paths are eagerly resolved via the current module resolver and embedded into the resultant AST. When it is evaluated later, import statement directly recall pre-resolved modules and the resolution process is not performed again.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_into_self_contained(t(), Scope.t(), String.t()) ::
+  @spec compile_into_self_contained(t(), Scope.t(), String.t(), keyword()) ::
           {:ok, AST.t()} | {:error, Rhai.Error.t()}
   def compile_into_self_contained(
         %__MODULE__{resource: resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
     with {:ok, ast_resource} <-
-           Rhai.Native.engine_compile_into_self_contained(resource, scope_resource, script) do
+           Rhai.Native.engine_compile_into_self_contained(
+             resource,
+             scope_resource,
+             script,
+             detailed_errors
+           ) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
@@ -260,16 +590,26 @@ defmodule Rhai.Engine do
   When passed a list of strings, first join the strings into one large script, and then compile them into an AST using own scope, which can be used later for evaluation.
 
   The scope is useful for passing constants into the script for optimization when using `:full` optimization level.
+
+  See the [compilation options](#module-compilation-options).
   """
-  @spec compile_scripts_with_scope(t(), Scope.t(), [String.t()]) ::
+  @spec compile_scripts_with_scope(t(), Scope.t(), [String.t()], keyword()) ::
           {:ok, AST.t()} | {:error, Rhai.Error.t()}
   def compile_scripts_with_scope(
         %__MODULE__{resource: resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
+    detailed_errors = Keyword.get(opts, :detailed_errors, false)
+
     with {:ok, ast_resource} <-
-           Rhai.Native.engine_compile_scripts_with_scope(resource, scope_resource, script) do
+           Rhai.Native.engine_compile_scripts_with_scope(
+             resource,
+             scope_resource,
+             script,
+             detailed_errors
+           ) do
       {:ok, AST.wrap_resource(ast_resource)}
     end
   end
@@ -287,96 +627,174 @@ defmodule Rhai.Engine do
 
   @doc """
   Evaluate a string as a script, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
+  """
+  @spec eval(t(), String.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
+  def eval(%__MODULE__{resource: resource}, script, opts \\ []) do
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval(resource, script, options)
+    end)
+  end
+
+  @doc """
+  Evaluate a string as a script without blocking the calling process.
+
+  The script runs on a thread pool owned by `rhai_rustler` instead of a BEAM scheduler.
+  A reference is returned immediately, and `{ref, result}` is sent to the calling process once the
+  evaluation completes, where `result` is what `eval/3` would have returned.
+
+  See the [evaluation options](#module-evaluation-options).
+
+  ## Example
+
+      ref = Rhai.Engine.eval_async(engine, "40 + 2")
+
+      receive do
+        {^ref, {:ok, value}} -> value
+      end
+
+  In a `GenServer`, the reply can be handled in `c:GenServer.handle_info/2`.
   """
-  @spec eval(t(), String.t()) :: {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
-  def eval(%__MODULE__{resource: resource}, script) do
-    Rhai.Native.engine_eval(resource, script)
+  @spec eval_async(t(), String.t(), keyword()) :: reference()
+  def eval_async(%__MODULE__{resource: resource}, script, opts \\ []) do
+    Rhai.Native.engine_eval_async(resource, script, eval_options(opts))
   end
 
   @doc """
   Evaluate a string as a script with own scope, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_with_scope(t(), Scope.t(), String.t()) ::
-          {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
+  @spec eval_with_scope(t(), Scope.t(), String.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
   def eval_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
-    Rhai.Native.engine_eval_with_scope(engine_resource, scope_resource, script)
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_with_scope(engine_resource, scope_resource, script, options)
+    end)
   end
 
   @doc """
   Evaluate an AST, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_ast(t(), AST.t()) :: {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
-  def eval_ast(%__MODULE__{resource: resource}, %AST{resource: ast_resource}) do
-    Rhai.Native.engine_eval_ast(resource, ast_resource)
+  @spec eval_ast(t(), AST.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
+  def eval_ast(%__MODULE__{resource: resource}, %AST{resource: ast_resource}, opts \\ []) do
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_ast(resource, ast_resource, options)
+    end)
   end
 
   @doc """
   Evaluate an AST with own scope, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_ast_with_scope(t(), Scope.t(), AST.t()) ::
-          {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
+  @spec eval_ast_with_scope(t(), Scope.t(), AST.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
   def eval_ast_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        %AST{resource: ast_resource}
+        %AST{resource: ast_resource},
+        opts \\ []
       ) do
-    Rhai.Native.engine_eval_ast_with_scope(engine_resource, scope_resource, ast_resource)
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_ast_with_scope(
+        engine_resource,
+        scope_resource,
+        ast_resource,
+        options
+      )
+    end)
   end
 
   @doc """
   Evaluate a string containing an expression, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_expression(t(), String.t()) :: {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
-  def eval_expression(%__MODULE__{resource: resource}, script) do
-    Rhai.Native.engine_eval_expression(resource, script)
+  @spec eval_expression(t(), String.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
+  def eval_expression(%__MODULE__{resource: resource}, script, opts \\ []) do
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_expression(resource, script, options)
+    end)
   end
 
   @doc """
   Evaluate a string containing an expression with own scope, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_expression_with_scope(t(), Scope.t(), String.t()) ::
-          {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
+  @spec eval_expression_with_scope(t(), Scope.t(), String.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
   def eval_expression_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
-    Rhai.Native.engine_eval_expression_with_scope(engine_resource, scope_resource, script)
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_expression_with_scope(
+        engine_resource,
+        scope_resource,
+        script,
+        options
+      )
+    end)
   end
 
   @doc """
   Evaluate a script file, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_file(t(), String.t()) :: {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
-  def eval_file(%__MODULE__{resource: resource}, path) do
-    Rhai.Native.engine_eval_file(resource, path)
+  @spec eval_file(t(), String.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
+  def eval_file(%__MODULE__{resource: resource}, path, opts \\ []) do
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_file(resource, path, options)
+    end)
   end
 
   @doc """
   Evaluate a script file with own scope, returning the result value or an error.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec eval_file_with_scope(t(), Scope.t(), String.t()) ::
-          {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
+  @spec eval_file_with_scope(t(), Scope.t(), String.t(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
   def eval_file_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        path
+        path,
+        opts \\ []
       ) do
-    Rhai.Native.engine_eval_file_with_scope(engine_resource, scope_resource, path)
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_eval_file_with_scope(engine_resource, scope_resource, path, options)
+    end)
   end
 
   @doc """
   Evaluate a string as script.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec run(t(), String.t()) :: :ok | {:error, Rhai.Error.t()}
-  def run(%__MODULE__{resource: resource}, script) do
-    with {:ok, _} <- Rhai.Native.engine_run(resource, script) do
-      :ok
-    end
+  @spec run(t(), String.t(), keyword()) :: eval_result(:ok | {:error, Rhai.Error.t()})
+  def run(%__MODULE__{resource: resource}, script, opts \\ []) do
+    evaluate(opts, fn options ->
+      with {:ok, _} <- Rhai.Native.engine_run(resource, script, options) do
+        :ok
+      end
+    end)
   end
 
   @doc """
@@ -385,51 +803,82 @@ defmodule Rhai.Engine do
   Constants Propagation
   If the optimization_level is not `:none` constants defined within the scope are propagated throughout the script including functions.
   This allows functions to be optimized based on dynamic global constants.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec run_with_scope(t(), Scope.t(), String.t()) :: :ok | {:error, Rhai.Error.t()}
+  @spec run_with_scope(t(), Scope.t(), String.t(), keyword()) ::
+          eval_result(:ok | {:error, Rhai.Error.t()})
   def run_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        script
+        script,
+        opts \\ []
       ) do
-    with {:ok, _} <- Rhai.Native.engine_run_with_scope(engine_resource, scope_resource, script) do
-      :ok
-    end
+    evaluate(opts, fn options ->
+      with {:ok, _} <-
+             Rhai.Native.engine_run_with_scope(
+               engine_resource,
+               scope_resource,
+               script,
+               options
+             ) do
+        :ok
+      end
+    end)
   end
 
   @doc """
   Evaluate an AST.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec run_ast(t(), AST.t()) :: :ok | {:error, Rhai.Error.t()}
-  def run_ast(%__MODULE__{resource: resource}, %AST{resource: ast_resource}) do
-    with {:ok, _} <- Rhai.Native.engine_run_ast(resource, ast_resource) do
-      :ok
-    end
+  @spec run_ast(t(), AST.t(), keyword()) :: eval_result(:ok | {:error, Rhai.Error.t()})
+  def run_ast(%__MODULE__{resource: resource}, %AST{resource: ast_resource}, opts \\ []) do
+    evaluate(opts, fn options ->
+      with {:ok, _} <- Rhai.Native.engine_run_ast(resource, ast_resource, options) do
+        :ok
+      end
+    end)
   end
 
   @doc """
   Evaluate an AST with own scope.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec run_ast_with_scope(t(), Scope.t(), AST.t()) :: :ok | {:error, Rhai.Error.t()}
+  @spec run_ast_with_scope(t(), Scope.t(), AST.t(), keyword()) ::
+          eval_result(:ok | {:error, Rhai.Error.t()})
   def run_ast_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        %AST{resource: ast_resource}
+        %AST{resource: ast_resource},
+        opts \\ []
       ) do
-    with {:ok, _} <-
-           Rhai.Native.engine_run_ast_with_scope(engine_resource, scope_resource, ast_resource) do
-      :ok
-    end
+    evaluate(opts, fn options ->
+      with {:ok, _} <-
+             Rhai.Native.engine_run_ast_with_scope(
+               engine_resource,
+               scope_resource,
+               ast_resource,
+               options
+             ) do
+        :ok
+      end
+    end)
   end
 
   @doc """
   Evaluate a file.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec run_file(t(), String.t()) :: :ok | {:error, Rhai.Error.t()}
-  def run_file(%__MODULE__{resource: resource}, path) do
-    with {:ok, _} <- Rhai.Native.engine_run_file(resource, path) do
-      :ok
-    end
+  @spec run_file(t(), String.t(), keyword()) :: eval_result(:ok | {:error, Rhai.Error.t()})
+  def run_file(%__MODULE__{resource: resource}, path, opts \\ []) do
+    evaluate(opts, fn options ->
+      with {:ok, _} <- Rhai.Native.engine_run_file(resource, path, options) do
+        :ok
+      end
+    end)
   end
 
   @doc """
@@ -438,32 +887,75 @@ defmodule Rhai.Engine do
   Constants Propagation
   If the optimization_level is not `:none` constants defined within the scope are propagated throughout the script including functions.
   This allows functions to be optimized based on dynamic global constants.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec run_file_with_scope(t(), Scope.t(), String.t()) :: :ok | {:error, Rhai.Error.t()}
+  @spec run_file_with_scope(t(), Scope.t(), String.t(), keyword()) ::
+          eval_result(:ok | {:error, Rhai.Error.t()})
   def run_file_with_scope(
         %__MODULE__{resource: engine_resource},
         %Scope{resource: scope_resource},
-        path
+        path,
+        opts \\ []
       ) do
-    with {:ok, _} <-
-           Rhai.Native.engine_run_file_with_scope(engine_resource, scope_resource, path) do
-      :ok
-    end
+    evaluate(opts, fn options ->
+      with {:ok, _} <-
+             Rhai.Native.engine_run_file_with_scope(
+               engine_resource,
+               scope_resource,
+               path,
+               options
+             ) do
+        :ok
+      end
+    end)
   end
 
   @doc """
   Call a script function defined in an AST with multiple arguments.
+
+  See the [evaluation options](#module-evaluation-options).
   """
-  @spec call_fn(t(), Scope.t(), AST.t(), String.t(), list()) ::
-          {:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()}
+  @spec call_fn(t(), Scope.t(), AST.t(), String.t(), list(), keyword()) ::
+          eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
   def call_fn(
         %__MODULE__{resource: resource},
         %Scope{resource: scope_resource},
         %AST{resource: ast_resource},
         name,
-        args
+        args,
+        opts \\ []
       ) do
-    Rhai.Native.engine_call_fn(resource, scope_resource, ast_resource, name, args)
+    evaluate(opts, fn options ->
+      Rhai.Native.engine_call_fn(resource, scope_resource, ast_resource, name, args, options)
+    end)
+  end
+
+  @doc """
+  Call a script function defined in an AST without blocking the calling process.
+
+  Works like `eval_async/3`: a reference is returned immediately, and `{ref, result}` is sent to the
+  calling process once the call completes, where `result` is what `call_fn/6` would have returned.
+
+  See the [evaluation options](#module-evaluation-options).
+  """
+  @spec call_fn_async(t(), Scope.t(), AST.t(), String.t(), list(), keyword()) :: reference()
+  def call_fn_async(
+        %__MODULE__{resource: resource},
+        %Scope{resource: scope_resource},
+        %AST{resource: ast_resource},
+        name,
+        args,
+        opts \\ []
+      ) do
+    Rhai.Native.engine_call_fn_async(
+      resource,
+      scope_resource,
+      ast_resource,
+      name,
+      args,
+      eval_options(opts)
+    )
   end
 
   @doc """
@@ -874,6 +1366,64 @@ defmodule Rhai.Engine do
     end
   end
 
+  @doc false
+  def evaluate(opts, eval) do
+    %{output: output, detailed_errors: detailed_errors} = options = eval_options(opts)
+    result = eval.(options)
+    result = if detailed_errors, do: detail_error(result), else: result
+
+    if output do
+      {result, Rhai.Native.output_buffer_take(output)}
+    else
+      result
+    end
+  end
+
+  defp file_resolver_options(opts) do
+    %{
+      base_path: Keyword.get(opts, :base_path),
+      extension: Keyword.get(opts, :extension),
+      cache: Keyword.get(opts, :cache, true),
+      sandbox: Keyword.get(opts, :sandbox, false)
+    }
+  end
+
+  defp eval_options(opts) do
+    cancel_token =
+      case Keyword.get(opts, :cancel_token) do
+        %Rhai.CancelToken{resource: resource} -> resource
+        nil -> nil
+      end
+
+    output = if Keyword.get(opts, :capture_output, false), do: Rhai.Native.output_buffer_new()
+
+    %{
+      cancel_token: cancel_token,
+      timeout: Keyword.get(opts, :timeout),
+      output: output,
+      detailed_errors: Keyword.get(opts, :detailed_errors, false),
+      map_keys: Keyword.get(opts, :map_keys, :strings),
+      tuples: Keyword.get(opts, :tuples, :arrays)
+    }
+  end
+
+  # Errors raised outside of the evaluation itself, such as a busy scope or a result that cannot be
+  # converted, are returned as tuples by the NIFs.
+  defp detail_error({:error, {type, message}}) do
+    {:error,
+     %{
+       type: type,
+       message: message,
+       line: nil,
+       column: nil,
+       source: nil,
+       function_stack: [],
+       inner: nil
+     }}
+  end
+
+  defp detail_error(result), do: result
+
   @doc false
   def wrap_resource(resource) do
     %__MODULE__{
@@ -13,6 +13,7 @@ defmodule Rhai.Error do
           | :index_not_found
           | :function_not_found
           | :module_not_found
+          | :module_forbidden
           | :in_function_call
           | :in_module
           | :unbound_this
@@ -38,6 +39,104 @@ defmodule Rhai.Error do
           | :scope_is_empty
           | :cannot_update_value_of_constant
           | :custom_operator
+          | :conversion
+          | :timeout
+          | :busy
+          | :loop_break
+          | :return
+          | :unknown
+          | :panic
 
-  @type t() :: {error(), String.t()}
+  @typedoc """
+  Kind of a `:parsing` error.
+
+  Errors in the script text, such as an open string, are reported by the kind of the lexer error.
+  """
+  @type parse_error ::
+          :unexpected_eof
+          | :bad_input
+          | :unexpected_input
+          | :unterminated_string
+          | :string_too_long
+          | :malformed_escape_sequence
+          | :malformed_number
+          | :malformed_char
+          | :malformed_identifier
+          | :improper_symbol
+          | :unknown_operator
+          | :missing_token
+          | :missing_symbol
+          | :malformed_call_expr
+          | :malformed_index_expr
+          | :malformed_in_expr
+          | :malformed_capture
+          | :duplicated_property
+          | :duplicated_switch_case
+          | :duplicated_variable
+          | :wrong_switch_integer_case
+          | :wrong_switch_default_case
+          | :wrong_switch_case_condition
+          | :property_expected
+          | :variable_expected
+          | :forbidden_variable
+          | :reserved
+          | :mismatched_type
+          | :expr_expected
+          | :wrong_doc_comment
+          | :wrong_fn_definition
+          | :fn_duplicated_definition
+          | :fn_missing_name
+          | :fn_missing_params
+          | :fn_duplicated_param
+          | :fn_missing_body
+          | :wrong_export
+          | :assignment_to_constant
+          | :assignment_to_invalid_lhs
+          | :variable_exists
+          | :variable_undefined
+          | :module_undefined
+          | :expr_too_deep
+          | :too_many_functions
+          | :literal_too_large
+          | :loop_break
+          | :unknown
+
+  @typedoc """
+  Error returned by evaluations and compilations with the `:detailed_errors` option.
+
+    * `:type` and `:message` - the type and the message of the error, without its position.
+    * `:line` and `:column` - the position of the error in the script, if known.
+    * `:source` - the source of the script the position refers to, if known (see `Rhai.AST.set_source/2`).
+    * `:function_stack` - the names of the script functions being executed, outermost first.
+    * `:inner` - the error raised in the called function for `:in_function_call` errors,
+      or in the imported module for `:in_module` errors.
+    * `:reason` - only for `:terminated` errors, the term given to `Rhai.CancelToken.cancel/2`.
+    * `:value` - only for `:runtime` errors, the value thrown by the script.
+    * `:kind` - only for `:parsing` errors, the kind of the error.
+  """
+  @type details() :: %{
+          required(:type) => error(),
+          required(:message) => String.t(),
+          required(:line) => pos_integer() | nil,
+          required(:column) => pos_integer() | nil,
+          required(:source) => String.t() | nil,
+          required(:function_stack) => [String.t()],
+          required(:inner) => details() | nil,
+          optional(:reason) => term(),
+          optional(:value) => Rhai.Any.t() | nil,
+          optional(:kind) => parse_error()
+        }
+
+  @typedoc """
+  Evaluations stopped with a `Rhai.CancelToken` return `{:terminated, message, reason}`,
+  where `reason` is the term given to `Rhai.CancelToken.cancel/2`.
+
+  Runtime errors return `{:runtime, message, value}`, where `value` is the value thrown by the script
+  with `throw`, or `nil` if it cannot be converted.
+  """
+  @type t() ::
+          {error(), String.t()}
+          | {:terminated, String.t(), term()}
+          | {:runtime, String.t(), Rhai.Any.t() | nil}
+          | details()
 end
@@ -0,0 +1,53 @@
+defmodule Rhai.FnPtr do
+  @moduledoc """
+  Handle to a Rhai function pointer or closure returned by a script.
+
+  Scripts can hand back callbacks, such as validators or reducers, which can be called from Elixir any number of times.
+  Function pointers can also be passed back as arguments to scripts evaluated with the AST that returned them.
+
+  ## Example
+
+      {:ok, validator} = Rhai.Engine.eval(engine, "let min = 3; |name| name.len() >= min")
+
+      {:ok, true} = Rhai.FnPtr.call(validator, engine, ["rhai"])
+      {:ok, false} = Rhai.FnPtr.call(validator, engine, ["ex"])
+
+  Function pointers keep the script-defined functions of the script that returned them, so both closures
+  and pointers to named functions (`Fn("name")`) can be called.
+  Function pointers read from a `Rhai.Scope` or passed to Elixir functions registered with
+  `Rhai.Engine.register_fn/4` do not, and can only call native functions.
+  """
+
+  defstruct [
+    # The name of the function.
+    name: nil,
+    # The actual NIF Resource.
+    resource: nil,
+    # Normally the compiler will happily do stuff like inlining the
+    # resource in attributes. This will convert the resource into an
+    # empty binary with no warning. This will make that harder to
+    # accidentaly do.
+    # It also serves as a handy way to tell file handles apart.
+    reference: nil
+  ]
+
+  @type t :: %__MODULE__{name: String.t()}
+
+  @doc """
+  Call the function pointer with the given arguments, in addition to the curried ones.
+
+  Accepts the same [evaluation options](Rhai.Engine.html#module-evaluation-options) as `Rhai.Engine.call_fn/6`.
+  """
+  @spec call(t(), Rhai.Engine.t(), list(), keyword()) ::
+          Rhai.Engine.eval_result({:ok, Rhai.Any.t()} | {:error, Rhai.Error.t()})
+  def call(
+        %__MODULE__{resource: resource},
+        %Rhai.Engine{resource: engine_resource},
+        args,
+        opts \\ []
+      ) do
+    Rhai.Engine.evaluate(opts, fn options ->
+      Rhai.Native.fn_ptr_call(resource, engine_resource, args, options)
+    end)
+  end
+end
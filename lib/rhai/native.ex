@@ -15,48 +15,61 @@ defmodule Rhai.Native do
     x86_64-pc-windows-gnu
   )
 
+  # Optional cargo features, such as "decimal", are not part of the precompiled NIFs.
+  features =
+    Application.compile_env(:rhai_rustler, :features) ||
+      String.split(System.get_env("RHAI_RUSTLER_FEATURES", ""), ",", trim: true)
+
   use RustlerPrecompiled,
     otp_app: :rhai_rustler,
     crate: "rhai_rustler",
     base_url: "https://github.com/rhaiscript/rhai_rustler/releases/download/v#{version}",
     force_build:
-      Application.compile_env(:rustler_precompiled, [:force_build, :rhai_rustler], false) ||
+      features != [] ||
+        Application.compile_env(:rustler_precompiled, [:force_build, :rhai_rustler], false) ||
         System.get_env("RHAI_RUSTLER_FORCE_BUILD") in ["1", "true"],
+    features: features,
     version: version,
     targets: targets
 
   # engine
   def engine_new, do: err()
   def engine_new_raw, do: err()
-  def engine_set_module_resolvers(_engine, _module_resolvers), do: err()
+  def engine_set_module_resolvers(_engine, _module_resolvers, _access), do: err()
   def engine_register_global_module(_engine, _path), do: err()
   def engine_register_static_module(_engine, _namespace, _path), do: err()
+  def engine_register_script_module(_engine, _namespace, _script, _options), do: err()
+  def engine_register_constants_module(_engine, _namespace, _constants, _tuples), do: err()
   def engine_register_custom_operator(_engine, _keyword, _precedence), do: err()
   def engine_register_package(_engine, _package), do: err()
-  def engine_compile(_engine, _script), do: err()
-  def engine_compile_with_scope(_engine, _scope, _script), do: err()
-  def engine_compile_expression(_engine, _script), do: err()
-  def engine_compile_expression_with_scope(_engine, _scope, _script), do: err()
-  def engine_compile_file(_engine, _path), do: err()
-  def engine_compile_file_with_scope(_engine, _scope, _path), do: err()
-  def engine_compile_into_self_contained(_engine, _scope, _script), do: err()
-  def engine_compile_scripts_with_scope(_engine, _scope, _scripts), do: err()
+  def engine_register_fn(_engine, _name, _arity, _pid, _timeout), do: err()
+  def engine_set_output(_engine, _pid), do: err()
+  def engine_compile(_engine, _script, _detailed_errors), do: err()
+  def engine_compile_with_scope(_engine, _scope, _script, _detailed_errors), do: err()
+  def engine_compile_expression(_engine, _script, _detailed_errors), do: err()
+  def engine_compile_expression_with_scope(_engine, _scope, _script, _detailed_errors), do: err()
+  def engine_compile_file(_engine, _path, _detailed_errors), do: err()
+  def engine_compile_file_with_scope(_engine, _scope, _path, _detailed_errors), do: err()
+  def engine_compile_into_self_contained(_engine, _scope, _script, _detailed_errors), do: err()
+  def engine_compile_scripts_with_scope(_engine, _scope, _scripts, _detailed_errors), do: err()
   def engine_compact_script(_engine, _script), do: err()
-  def engine_eval(_engine, _script), do: err()
-  def engine_eval_with_scope(_engine, _scope, _script), do: err()
-  def engine_eval_ast(_engine, _ast), do: err()
-  def engine_eval_ast_with_scope(_engine, _scope, _ast), do: err()
-  def engine_eval_expression(_engine, _script), do: err()
-  def engine_eval_expression_with_scope(_engine, _scope, _script), do: err()
-  def engine_eval_file(_engine, _path), do: err()
-  def engine_eval_file_with_scope(_engine, _scope, _path), do: err()
-  def engine_run(_engine, _script), do: err()
-  def engine_run_with_scope(_engine, _scope, _script), do: err()
-  def engine_run_ast(_engine, _ast), do: err()
-  def engine_run_ast_with_scope(_engine, _scope, _ast), do: err()
-  def engine_run_file(_engine, _path), do: err()
-  def engine_run_file_with_scope(_engine, _scope, _path), do: err()
-  def engine_call_fn(_engine, _scope, _ast, _name, _args), do: err()
+  def engine_eval(_engine, _script, _options), do: err()
+  def engine_eval_async(_engine, _script, _options), do: err()
+  def engine_eval_with_scope(_engine, _scope, _script, _options), do: err()
+  def engine_eval_ast(_engine, _ast, _options), do: err()
+  def engine_eval_ast_with_scope(_engine, _scope, _ast, _options), do: err()
+  def engine_eval_expression(_engine, _script, _options), do: err()
+  def engine_eval_expression_with_scope(_engine, _scope, _script, _options), do: err()
+  def engine_eval_file(_engine, _path, _options), do: err()
+  def engine_eval_file_with_scope(_engine, _scope, _path, _options), do: err()
+  def engine_run(_engine, _script, _options), do: err()
+  def engine_run_with_scope(_engine, _scope, _script, _options), do: err()
+  def engine_run_ast(_engine, _ast, _options), do: err()
+  def engine_run_ast_with_scope(_engine, _scope, _ast, _options), do: err()
+  def engine_run_file(_engine, _path, _options), do: err()
+  def engine_run_file_with_scope(_engine, _scope, _path, _options), do: err()
+  def engine_call_fn(_engine, _scope, _ast, _name, _args, _options), do: err()
+  def engine_call_fn_async(_engine, _scope, _ast, _name, _args, _options), do: err()
   def engine_set_fail_on_invalid_map_property(_engine, _flag), do: err()
   def engine_fail_on_invalid_map_property(_engine), do: err()
   def engine_set_max_array_size(_engine, _flag), do: err()
@@ -98,8 +111,8 @@ defmodule Rhai.Native do
   def engine_disable_symbol(_engine, _symbol), do: err()
   def engine_ensure_data_size_within_limits(_engine, _value), do: err()
   # scope
-  def scope_new, do: err()
-  def scope_with_capacity(_capacity), do: err()
+  def scope_new(_tuples), do: err()
+  def scope_with_capacity(_capacity, _tuples), do: err()
   def scope_push_dynamic(_scope, _name, _value), do: err()
   def scope_push_constant_dynamic(_scope, _name, _value), do: err()
   def scope_contains(_scope, _name), do: err()
@@ -127,6 +140,26 @@ defmodule Rhai.Native do
   def ast_clear_statements(_ast), do: err()
   def ast_clone_functions_only(_ast), do: err()
   def ast_has_functions(_ast), do: err()
+  # cancel_token
+  def cancel_token_new, do: err()
+  def cancel_token_cancel(_cancel_token, _reason), do: err()
+  def cancel_token_cancelled(_cancel_token), do: err()
+  # fn_ptr
+  def fn_ptr_call(_fn_ptr, _engine, _args, _options), do: err()
+  # module_resolver
+  def static_module_resolver_new, do: err()
+  def static_module_resolver_add(_resolver, _engine, _path, _source, _options), do: err()
+  def static_module_resolver_remove(_resolver, _path), do: err()
+  def static_module_resolver_paths(_resolver), do: err()
+  def static_module_resolver_contains_path(_resolver, _path), do: err()
+  def process_module_resolver_new(_pid, _timeout, _cache), do: err()
+  def process_module_resolver_invalidate(_resolver, _path), do: err()
+  def process_module_resolver_invalidate_all(_resolver), do: err()
+  # output
+  def output_buffer_new, do: err()
+  def output_buffer_take(_output_buffer), do: err()
+  # callback
+  def callback_reply(_callback, _reply), do: err()
 
   defp err, do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -13,9 +13,12 @@ defmodule Rhai.Package do
           | :basic_string
           | :basic_time
           | :bit_field
+          | :collections
           | :core
+          | :date_time
           | :language_core
           | :logic
           | :more_string
+          | :opaque
           | :standard
 end
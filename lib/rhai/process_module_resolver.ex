@@ -0,0 +1,92 @@
+defmodule Rhai.ProcessModuleResolver do
+  @moduledoc """
+  Module resolver asking an Elixir process for the modules imported by scripts.
+
+  On `import "path"`, the process receives a `{:rhai_import, callback, path}` message and must answer it with
+  `Rhai.Callback.reply/2`, replying `{:ok, source}` with the source of the module, `{:ok, ast}` with a
+  compiled `Rhai.AST`, or `{:error, reason}` if there is no such module.
+  The module is evaluated with the engine running the script, and cached by path until it is invalidated.
+
+  The import fails with a `:module_not_found` error if the reply is an error, if the process is not alive
+  or if it does not reply in time, and with the error of the module if it fails to compile or evaluate.
+  Waiting for the process also stops at the `:timeout` of the evaluation.
+  As the script is blocked while waiting for the reply, the process must not be the one evaluating it.
+
+  Each pending import also blocks the dirty CPU scheduler running the script, so the process should
+  reply with the source of the module, which is compiled by the waiting script, or with an AST compiled
+  ahead of time. Compiling the module when the request arrives, with `Rhai.Engine.compile/3` or any
+  other dirty NIF, deadlocks once there are as many pending imports as dirty CPU schedulers: the compilation
+  waits for a scheduler that only the imports would free, and they fail at their timeout.
+
+  ## Example
+
+      def handle_info({:rhai_import, callback, path}, state) do
+        case Repo.get_by(Script, path: path) do
+          %Script{source: source} -> Rhai.Callback.reply(callback, {:ok, source})
+          nil -> Rhai.Callback.reply(callback, {:error, :not_found})
+        end
+
+        {:noreply, state}
+      end
+
+      resolver = Rhai.ProcessModuleResolver.new(pid)
+      engine = Rhai.Engine.set_module_resolvers(engine, [resolver])
+  """
+
+  defstruct [
+    # The actual NIF Resource.
+    resource: nil,
+    # Normally the compiler will happily do stuff like inlining the
+    # resource in attributes. This will convert the resource into an
+    # empty binary with no warning. This will make that harder to
+    # accidentaly do.
+    # It also serves as a handy way to tell file handles apart.
+    reference: nil
+  ]
+
+  @type t :: %__MODULE__{}
+
+  @doc """
+  Create a resolver asking `pid` for modules.
+
+  ## Options
+
+    * `:timeout` - the maximum time in milliseconds to wait for a reply, defaults to `5000`.
+    * `:cache` - whether resolved modules are cached until they are invalidated, defaults to `true`.
+  """
+  @spec new(pid(), keyword()) :: t()
+  def new(pid, opts \\ []) do
+    timeout = Keyword.get(opts, :timeout, 5000)
+    cache = Keyword.get(opts, :cache, true)
+
+    wrap_resource(Rhai.Native.process_module_resolver_new(pid, timeout, cache))
+  end
+
+  @doc """
+  Remove the module cached under `path`, so the process is asked for it again on the next import.
+  """
+  @spec invalidate(t(), String.t()) :: t()
+  def invalidate(%__MODULE__{resource: resource} = resolver, path) do
+    Rhai.Native.process_module_resolver_invalidate(resource, path)
+
+    resolver
+  end
+
+  @doc """
+  Remove all the cached modules.
+  """
+  @spec invalidate_all(t()) :: t()
+  def invalidate_all(%__MODULE__{resource: resource} = resolver) do
+    Rhai.Native.process_module_resolver_invalidate_all(resource)
+
+    resolver
+  end
+
+  @doc false
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
@@ -2,6 +2,9 @@ defmodule Rhai.Scope do
   @moduledoc """
   Type containing information about the current scope. Useful for keeping state between Engine evaluation runs.
   Scope implements the [https://hexdocs.pm/elixir/1.12/Enumerable.html](Enumerable) protocol.
+
+  A scope can only be used by one evaluation at a time: using it while an evaluation is running with it
+  fails with a `{:busy, message}` error.
   """
 
   defstruct [
@@ -17,30 +20,56 @@ defmodule Rhai.Scope do
 
   @type t :: %__MODULE__{}
 
+  @typedoc """
+  How the tuples of the values pushed to the scope are passed to scripts.
+
+    * `:arrays` - tuples are arrays, returned as lists, so `{:ok, 1}` is returned as `["ok", 1]`.
+    * `:tagged` - tuples are `Tuple` values, which scripts can index and iterate like arrays, and
+      convert with `to_array()`, and which are returned as tuples.
+
+  Whatever the strategy, keyword lists are object maps, as in `opts.limit` or `opts.keys()`, which
+  are returned as keyword lists, in the same order. Keys set by scripts are appended, and must be
+  existing atoms to be returned. Keyword lists with duplicate keys are passed as lists.
+  """
+  @type tuples :: :arrays | :tagged
+
   @doc """
   Create a new Scope
+
+  ## Options
+
+    * `:tuples` - how tuples are converted, `:arrays` (the default) or `:tagged`. See `t:tuples/0`.
   """
-  @spec new :: t()
-  def new do
-    wrap_resource(Rhai.Native.scope_new())
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    opts
+    |> Keyword.get(:tuples, :arrays)
+    |> Rhai.Native.scope_new()
+    |> wrap_resource()
   end
 
   @doc """
   Create a new Scope with a particular capacity.
+
+  Takes the same options as `new/1`.
   """
-  @spec with_capacity(non_neg_integer()) :: t()
-  def with_capacity(capacity) do
+  @spec with_capacity(non_neg_integer(), keyword()) :: t()
+  def with_capacity(capacity, opts \\ []) do
     capacity
-    |> Rhai.Native.scope_with_capacity()
+    |> Rhai.Native.scope_with_capacity(Keyword.get(opts, :tuples, :arrays))
     |> wrap_resource()
   end
 
   @doc """
   Add (push) a new entry to the Scope.
+
+  Raises `ArgumentError` if the value cannot be converted into a Rhai value.
   """
   @spec push(t(), String.t(), Rhai.Any.t()) :: t()
   def push(%__MODULE__{resource: resource} = scope, name, value) do
-    Rhai.Native.scope_push_dynamic(resource, name, value)
+    resource
+    |> Rhai.Native.scope_push_dynamic(name, value)
+    |> unwrap!()
 
     scope
   end
@@ -50,10 +79,14 @@ defmodule Rhai.Scope do
 
   Constants are immutable and cannot be assigned to. Their values never change.
   Constants propagation is a technique used to optimize an AST.
+
+  Raises `ArgumentError` if the value cannot be converted into a Rhai value.
   """
   @spec push_constant(t(), String.t(), Rhai.Any.t()) :: t()
   def push_constant(%__MODULE__{resource: resource} = scope, name, value) do
-    Rhai.Native.scope_push_constant_dynamic(resource, name, value)
+    resource
+    |> Rhai.Native.scope_push_constant_dynamic(name, value)
+    |> unwrap!()
 
     scope
   end
@@ -78,10 +111,14 @@ defmodule Rhai.Scope do
 
   @doc """
   Get the value of an entry in the Scope, starting from the last.
+
+  Raises `ArgumentError` if the value cannot be converted into an Elixir term.
   """
   @spec get_value(t(), String.t()) :: nil | Rhai.Any.t()
   def get_value(%__MODULE__{resource: resource}, name) do
-    Rhai.Native.scope_get_value(resource, name)
+    resource
+    |> Rhai.Native.scope_get_value(name)
+    |> unwrap!()
   end
 
   @doc """
@@ -124,10 +161,13 @@ defmodule Rhai.Scope do
   Remove the last entry in the Scope by the specified name and return its value.
 
   If the entry by the specified name is not found, None is returned.
+  Raises `ArgumentError` if the value cannot be converted into an Elixir term.
   """
   @spec remove(t(), String.t()) :: nil | Rhai.Any.t()
   def remove(%__MODULE__{resource: resource}, name) do
-    Rhai.Native.scope_remove(resource, name)
+    resource
+    |> Rhai.Native.scope_remove(name)
+    |> unwrap!()
   end
 
   @doc """
@@ -145,7 +185,7 @@ defmodule Rhai.Scope do
 
   Returns an error if the Scope is empty.
   """
-  @spec pop(t()) :: {:ok, t()} | {:error, {:scope_is_empty, String.t()}}
+  @spec pop(t()) :: {:ok, t()} | {:error, {:scope_is_empty | :busy, String.t()}}
   def pop(%__MODULE__{resource: resource} = scope) do
     case Rhai.Native.scope_pop(resource) do
       {:ok, _} ->
@@ -167,7 +207,7 @@ defmodule Rhai.Scope do
       {:ok, _} ->
         scope
 
-      {:error, {:scope_is_empty, message}} ->
+      {:error, {_, message}} ->
         raise message
     end
   end
@@ -178,10 +218,11 @@ defmodule Rhai.Scope do
   Search starts backwards from the last, and only the first entry matching the specified name is updated.
   If no entry matching the specified name is found, a new one is added.
 
-  Returns an error when trying to update the value of a constant.
+  Returns an error when trying to update the value of a constant or if the value cannot be converted into a Rhai value.
   """
   @spec set_value(t(), String.t(), Rhai.Any.t()) ::
-          {:ok, t()} | {:error, {:cannot_update_value_of_constant, String.t()}}
+          {:ok, t()}
+          | {:error, {:cannot_update_value_of_constant | :conversion | :busy, String.t()}}
   def set_value(%__MODULE__{resource: resource} = scope, name, value) do
     case Rhai.Native.scope_set_value(resource, name, value) do
       {:ok, _} ->
@@ -198,7 +239,7 @@ defmodule Rhai.Scope do
   Search starts backwards from the last, and only the first entry matching the specified name is updated.
   If no entry matching the specified name is found, a new one is added.
 
-  Raises when trying to update the value of a constant.
+  Raises when trying to update the value of a constant or if the value cannot be converted into a Rhai value.
   """
   @spec set_value!(t(), String.t(), Rhai.Any.t()) :: t()
   def set_value!(%__MODULE__{} = scope, name, value) do
@@ -206,7 +247,10 @@ defmodule Rhai.Scope do
       {:ok, _} ->
         scope
 
-      {:error, {:cannot_update_value_of_constant, message}} ->
+      {:error, {:conversion, message}} ->
+        raise ArgumentError, message
+
+      {:error, {_, message}} ->
         raise message
     end
   end
@@ -216,10 +260,14 @@ defmodule Rhai.Scope do
   Push a new entry with the value into the Scope if the name doesn’t exist or if the existing entry is constant.
 
   Search starts backwards from the last, and only the first entry matching the specified name is updated.
+
+  Raises `ArgumentError` if the value cannot be converted into a Rhai value.
   """
   @spec set_or_push(t(), String.t(), Rhai.Any.t()) :: t()
   def set_or_push(%__MODULE__{resource: resource} = scope, name, value) do
-    Rhai.Native.scope_set_or_push(resource, name, value)
+    resource
+    |> Rhai.Native.scope_set_or_push(name, value)
+    |> unwrap!()
 
     scope
   end
@@ -232,6 +280,11 @@ defmodule Rhai.Scope do
     }
   end
 
+  @doc false
+  def unwrap!({:ok, value}), do: value
+  def unwrap!({:error, {:conversion, message}}), do: raise(ArgumentError, message)
+  def unwrap!({:error, {:busy, message}}), do: raise(message)
+
   defimpl Enumerable do
     def count(scope) do
       {:ok, Rhai.Scope.len(scope)}
@@ -244,6 +297,7 @@ defmodule Rhai.Scope do
     def reduce(%Rhai.Scope{resource: resource}, acc, fun) do
       resource
       |> Rhai.Native.scope_iter_collect()
+      |> Rhai.Scope.unwrap!()
       |> Enumerable.List.reduce(acc, fun)
     end
 
@@ -0,0 +1,105 @@
+defmodule Rhai.StaticModuleResolver do
+  @moduledoc """
+  Module resolver serving modules compiled from sources registered by Elixir, such as scripts stored in a database.
+
+  Each module is registered under the path scripts import it with. Modules can be added, replaced or removed at any
+  time, and engines using the resolver see the changes on their next `import`.
+
+  ## Example
+
+      resolver = Rhai.StaticModuleResolver.new()
+      source = ~s|fn greet(name) { "Hello, " + name }|
+
+      {:ok, resolver} = Rhai.StaticModuleResolver.add(resolver, engine, "greeter", source)
+
+      engine = Rhai.Engine.set_module_resolvers(engine, [resolver])
+
+      {:ok, "Hello, Rhai"} = Rhai.Engine.eval(engine, ~s|import "greeter" as g; g::greet("Rhai")|)
+  """
+
+  defstruct [
+    # The actual NIF Resource.
+    resource: nil,
+    # Normally the compiler will happily do stuff like inlining the
+    # resource in attributes. This will convert the resource into an
+    # empty binary with no warning. This will make that harder to
+    # accidentaly do.
+    # It also serves as a handy way to tell file handles apart.
+    reference: nil
+  ]
+
+  @type t :: %__MODULE__{}
+
+  @doc """
+  Create a new resolver without modules.
+  """
+  @spec new :: t()
+  def new do
+    wrap_resource(Rhai.Native.static_module_resolver_new())
+  end
+
+  @doc """
+  Compile and evaluate `source` with the engine and register the resulting module under `path`.
+
+  A module already registered under `path` is replaced. The module is registered only if its source
+  compiles and evaluates without errors.
+
+  Accepts the same [evaluation options](Rhai.Engine.html#module-evaluation-options) as `Rhai.Engine.eval/3`.
+  """
+  @spec add(t(), Rhai.Engine.t(), String.t(), String.t(), keyword()) ::
+          Rhai.Engine.eval_result({:ok, t()} | {:error, Rhai.Error.t()})
+  def add(
+        %__MODULE__{resource: resource} = resolver,
+        %Rhai.Engine{resource: engine_resource},
+        path,
+        source,
+        opts \\ []
+      ) do
+    Rhai.Engine.evaluate(opts, fn options ->
+      with {:ok, _} <-
+             Rhai.Native.static_module_resolver_add(
+               resource,
+               engine_resource,
+               path,
+               source,
+               options
+             ) do
+        {:ok, resolver}
+      end
+    end)
+  end
+
+  @doc """
+  Remove the module registered under `path`, if any.
+  """
+  @spec remove(t(), String.t()) :: t()
+  def remove(%__MODULE__{resource: resource} = resolver, path) do
+    Rhai.Native.static_module_resolver_remove(resource, path)
+
+    resolver
+  end
+
+  @doc """
+  Return the paths of the registered modules, in sorted order.
+  """
+  @spec paths(t()) :: [String.t()]
+  def paths(%__MODULE__{resource: resource}) do
+    Rhai.Native.static_module_resolver_paths(resource)
+  end
+
+  @doc """
+  Return true if a module is registered under `path`.
+  """
+  @spec contains_path?(t(), String.t()) :: boolean()
+  def contains_path?(%__MODULE__{resource: resource}, path) do
+    Rhai.Native.static_module_resolver_contains_path(resource, path)
+  end
+
+  @doc false
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
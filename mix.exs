@@ -19,6 +19,7 @@ defmodule Rhai.MixProject do
 
   def application do
     [
+      mod: {Rhai.Application, []},
       extra_applications: [:logger]
     ]
   end
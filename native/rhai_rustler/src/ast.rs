@@ -1,39 +1,57 @@
-use std::sync::Mutex;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use rhai::AST;
-use rustler::{Resource, ResourceArc};
+use rustler::{NifResult, Resource, ResourceArc};
+
+use crate::{error::BusyError, lock};
 
 pub struct ASTResource {
-    pub ast: Mutex<AST>,
+    pub ast: RwLock<AST>,
 }
 
 #[rustler::resource_impl]
 impl Resource for ASTResource {}
 
+impl ASTResource {
+    /// Evaluations only need a shared reference to the AST, so any number of them can run at once.
+    pub fn read(&self) -> RwLockReadGuard<'_, AST> {
+        lock::read(&self.ast)
+    }
+
+    /// Updating the AST fails while it is being evaluated.
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, AST>, BusyError> {
+        lock::try_write(&self.ast, "AST")
+    }
+}
+
 #[rustler::nif]
 fn ast_empty() -> ResourceArc<ASTResource> {
     ResourceArc::new(ASTResource {
-        ast: Mutex::new(AST::empty()),
+        ast: RwLock::new(AST::empty()),
     })
 }
 
 #[rustler::nif]
-fn ast_set_source(resource: ResourceArc<ASTResource>, source: &str) {
-    let mut ast = resource.ast.try_lock().unwrap();
+fn ast_set_source(resource: ResourceArc<ASTResource>, source: &str) -> NifResult<()> {
+    let mut ast = resource.write()?;
 
     ast.set_source(source);
+
+    Ok(())
 }
 
 #[rustler::nif]
-fn ast_clear_source(resource: ResourceArc<ASTResource>) {
-    let mut ast = resource.ast.try_lock().unwrap();
+fn ast_clear_source(resource: ResourceArc<ASTResource>) -> NifResult<()> {
+    let mut ast = resource.write()?;
 
     ast.clear_source();
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn ast_source(resource: ResourceArc<ASTResource>) -> Option<String> {
-    let ast = resource.ast.try_lock().unwrap();
+    let ast = resource.read();
 
     ast.source().map(|s| s.to_string())
 }
@@ -43,11 +61,11 @@ fn ast_merge(
     resource: ResourceArc<ASTResource>,
     other_resource: ResourceArc<ASTResource>,
 ) -> ResourceArc<ASTResource> {
-    let ast = resource.ast.try_lock().unwrap();
-    let other_ast = other_resource.ast.try_lock().unwrap();
+    let ast = resource.read();
+    let other_ast = other_resource.read();
 
     ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast.merge(&other_ast)),
+        ast: RwLock::new(ast.merge(&other_ast)),
     })
 }
 
@@ -55,41 +73,45 @@ fn ast_merge(
 fn ast_combine(
     resource: ResourceArc<ASTResource>,
     other_resource: ResourceArc<ASTResource>,
-) -> ResourceArc<ASTResource> {
-    let mut ast = resource.ast.try_lock().unwrap();
-    let other_ast = other_resource.ast.try_lock().unwrap().clone();
+) -> NifResult<ResourceArc<ASTResource>> {
+    let other_ast = other_resource.read().clone();
+    let mut ast = resource.write()?;
 
-    ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast.combine(other_ast).clone()),
-    })
+    Ok(ResourceArc::new(ASTResource {
+        ast: RwLock::new(ast.combine(other_ast).clone()),
+    }))
 }
 
 #[rustler::nif]
-fn ast_clear_functions(resource: ResourceArc<ASTResource>) {
-    let mut ast = resource.ast.try_lock().unwrap();
+fn ast_clear_functions(resource: ResourceArc<ASTResource>) -> NifResult<()> {
+    let mut ast = resource.write()?;
 
     ast.clear_functions();
+
+    Ok(())
 }
 
 #[rustler::nif]
-fn ast_clear_statements(resource: ResourceArc<ASTResource>) {
-    let mut ast = resource.ast.try_lock().unwrap();
+fn ast_clear_statements(resource: ResourceArc<ASTResource>) -> NifResult<()> {
+    let mut ast = resource.write()?;
 
     ast.clear_statements();
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn ast_clone_functions_only(resource: ResourceArc<ASTResource>) -> ResourceArc<ASTResource> {
-    let ast = resource.ast.try_lock().unwrap();
+    let ast = resource.read();
 
     ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast.clone_functions_only()),
+        ast: RwLock::new(ast.clone_functions_only()),
     })
 }
 
 #[rustler::nif]
 fn ast_has_functions(resource: ResourceArc<ASTResource>) -> bool {
-    let ast = resource.ast.try_lock().unwrap();
+    let ast = resource.read();
 
     ast.has_functions()
 }
@@ -0,0 +1,206 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Condvar, Mutex, OnceLock, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rhai::{Dynamic, EvalAltResult, Position};
+use rustler::{
+    thread::is_scheduler_thread, Atom, Env, LocalPid, OwnedEnv, Resource, ResourceArc, Term,
+};
+
+use crate::{
+    ast::ASTResource,
+    error::ConversionError,
+    eval_options::{termination, tuples},
+    lock,
+    types::{to_dynamic_with, Tuples},
+};
+
+mod atoms {
+    rustler::atoms! {
+        ok,
+        error
+    }
+}
+
+/// Interval at which a pending call checks whether its evaluation was cancelled or timed out.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub enum CallbackError {
+    NotAlive,
+    Timeout,
+    Terminated,
+    Reply(String),
+    Conversion(ConversionError),
+}
+
+impl CallbackError {
+    /// The error failing the script that called the function `name`.
+    ///
+    /// A call interrupted by the cancellation or the deadline of the evaluation terminates the
+    /// script instead.
+    pub fn into_eval_error(
+        self,
+        name: &str,
+        timeout: Duration,
+        position: Position,
+    ) -> Box<EvalAltResult> {
+        if let Some(value) = termination() {
+            return Box::new(EvalAltResult::ErrorTerminated(value, position));
+        }
+
+        let message = match self {
+            CallbackError::NotAlive => format!("Function '{}' handler is not alive", name),
+            CallbackError::Timeout => format!(
+                "Function '{}' timed out after {} ms",
+                name,
+                timeout.as_millis()
+            ),
+            CallbackError::Terminated => format!("Function '{}' was interrupted", name),
+            CallbackError::Reply(reason) => reason,
+            CallbackError::Conversion(err) => err.to_string(),
+        };
+
+        Box::new(EvalAltResult::ErrorRuntime(message.into(), position))
+    }
+}
+
+pub struct CallbackResource {
+    pub reply: Mutex<Option<Result<Dynamic, String>>>,
+    pub condvar: Condvar,
+    /// How tuples in the reply are converted, as it is converted outside of the evaluation.
+    pub tuples: Tuples,
+}
+
+#[rustler::resource_impl]
+impl Resource for CallbackResource {}
+
+type SendJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Thread sending the messages of (dirty) scheduler threads, started on first use.
+static SENDER: OnceLock<mpsc::Sender<SendJob>> = OnceLock::new();
+
+fn start_sender() -> mpsc::Sender<SendJob> {
+    let (sender, receiver) = mpsc::channel::<SendJob>();
+
+    thread::Builder::new()
+        .name("rhai_rustler_sender".to_string())
+        .spawn(move || {
+            for job in receiver {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+        })
+        .expect("failed to start the rhai_rustler sender thread");
+
+    sender
+}
+
+/// Send a message to an Elixir process.
+///
+/// The VM only allows sending from an `OwnedEnv` on threads it does not manage, so when called
+/// from a (dirty) scheduler thread the message is handed to a long-lived sender thread, which
+/// sends the messages in the order they are handed over.
+pub fn send<F>(pid: LocalPid, message: F) -> Result<(), CallbackError>
+where
+    F: for<'a> FnOnce(Env<'a>) -> Result<Term<'a>, ConversionError>,
+{
+    let mut owned_env = OwnedEnv::new();
+    let message = owned_env
+        .run(|env| message(env).map(|message| owned_env.save(message)))
+        .map_err(CallbackError::Conversion)?;
+
+    let mut send = move || {
+        owned_env
+            .send_and_clear(&pid, |env| message.load(env))
+            .is_ok()
+    };
+    let sent = if is_scheduler_thread() {
+        let (sent, result) = mpsc::sync_channel(1);
+        let job = Box::new(move || {
+            let _ = sent.send(send());
+        });
+
+        SENDER.get_or_init(start_sender).send(job).is_ok() && result.recv().unwrap_or(false)
+    } else {
+        send()
+    };
+
+    if sent {
+        Ok(())
+    } else {
+        Err(CallbackError::NotAlive)
+    }
+}
+
+/// Send a request to an Elixir process and block until it replies with `Rhai.Callback.reply/2`,
+/// the timeout, if any, elapses or the evaluation running on the current thread is terminated.
+///
+/// The message is built by `message`, which receives the callback resource the reply must be sent to.
+pub fn call<F>(
+    pid: LocalPid,
+    timeout: Option<Duration>,
+    message: F,
+) -> Result<Dynamic, CallbackError>
+where
+    F: for<'a> FnOnce(Env<'a>, ResourceArc<CallbackResource>) -> Result<Term<'a>, ConversionError>,
+{
+    let callback = ResourceArc::new(CallbackResource {
+        reply: Mutex::new(None),
+        condvar: Condvar::new(),
+        tuples: tuples(),
+    });
+
+    let request = callback.clone();
+    send(pid, move |env| message(env, request))?;
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut reply = lock::lock(&callback.reply);
+
+    // The wait is sliced, so that cancelling the evaluation does not wait for the reply.
+    loop {
+        if let Some(reply) = reply.take() {
+            return reply.map_err(CallbackError::Reply);
+        }
+
+        let now = Instant::now();
+
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(CallbackError::Timeout);
+        }
+
+        if termination().is_some() {
+            return Err(CallbackError::Terminated);
+        }
+
+        (reply, _) = callback
+            .condvar
+            .wait_timeout(
+                reply,
+                deadline.map_or(POLL_INTERVAL, |deadline| POLL_INTERVAL.min(deadline - now)),
+            )
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+#[rustler::nif]
+fn callback_reply<'a>(env: Env<'a>, resource: ResourceArc<CallbackResource>, reply: Term<'a>) {
+    let reply = match reply.decode::<(Atom, Term)>() {
+        Ok((tag, value)) if tag == atoms::ok() => {
+            match value.decode::<ResourceArc<ASTResource>>() {
+                // Compiled modules replied to module resolvers.
+                Ok(ast) => Ok(Dynamic::from(ast)),
+                Err(_) => to_dynamic_with(env, &value, resource.tuples)
+                    .map_err(|err| err.within("reply").to_string()),
+            }
+        }
+        Ok((tag, reason)) if tag == atoms::error() => Err(reason
+            .decode::<String>()
+            .unwrap_or_else(|_| format!("{:?}", reason))),
+        _ => Err(format!("Invalid reply: {:?}", reply)),
+    };
+
+    *lock::lock(&resource.reply) = Some(reply);
+    resource.condvar.notify_one();
+}
@@ -0,0 +1,55 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use rustler::{Env, Resource, ResourceArc, Term};
+
+use crate::lock;
+
+pub struct CancelTokenResource {
+    pub cancelled: AtomicBool,
+    /// The reason term, stored in the external term format so it can outlive the calling process env.
+    pub reason: Mutex<Option<Vec<u8>>>,
+}
+
+#[rustler::resource_impl]
+impl Resource for CancelTokenResource {}
+
+impl CancelTokenResource {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn reason<'a>(&self, env: Env<'a>) -> Term<'a> {
+        lock::lock(&self.reason)
+            .as_deref()
+            .and_then(|reason| env.binary_to_term(reason))
+            .map(|(reason, _)| reason)
+            .unwrap_or_else(|| rustler::types::atom::nil().to_term(env))
+    }
+}
+
+#[rustler::nif]
+fn cancel_token_new() -> ResourceArc<CancelTokenResource> {
+    ResourceArc::new(CancelTokenResource {
+        cancelled: AtomicBool::new(false),
+        reason: Mutex::new(None),
+    })
+}
+
+#[rustler::nif]
+fn cancel_token_cancel(resource: ResourceArc<CancelTokenResource>, reason: Term) {
+    let mut stored_reason = lock::lock(&resource.reason);
+
+    // Only the first cancellation is recorded.
+    if !resource.is_cancelled() {
+        *stored_reason = Some(reason.to_binary().as_slice().to_vec());
+        resource.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[rustler::nif]
+fn cancel_token_cancelled(resource: ResourceArc<CancelTokenResource>) -> bool {
+    resource.is_cancelled()
+}
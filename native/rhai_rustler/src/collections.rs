@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use rhai::{def_package, plugin::*, Array, EvalAltResult, Position, INT};
+
+use crate::lock;
+
+/// An Elixir tuple, passed to scripts with the `:tagged` tuple strategy so that it is returned as
+/// a tuple instead of a list. Scripts can index it like an array, but not modify it.
+#[derive(Clone, Debug)]
+pub struct Tuple(pub Vec<Dynamic>);
+
+impl IntoIterator for Tuple {
+    type Item = Dynamic;
+    type IntoIter = std::vec::IntoIter<Dynamic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let items = self
+            .0
+            .iter()
+            .map(|item| format!("{:?}", item))
+            .collect::<Vec<_>>();
+
+        write!(f, "{{{}}}", items.join(", "))
+    }
+}
+
+/// Tag of the object maps converted from keyword lists with sorted keys, the order of the keys of
+/// object maps.
+const SORTED_KEYWORD_LIST: i32 = -1;
+
+/// Number of key orders of keyword lists remembered, beyond which keyword lists are returned with
+/// their keys sorted.
+const MAX_KEYWORD_ORDERS: usize = 65_536;
+
+/// Orders of the keys of the keyword lists converted to object maps, which are sorted by key.
+///
+/// The object maps are tagged with the index of the order of their keys, as a negative number so
+/// that it is not mistaken for the kinds of the keys of other maps. Orders are shared by the
+/// keyword lists with the same keys in the same order, and are never forgotten, like the atoms of
+/// their keys.
+#[derive(Default)]
+struct KeywordOrders {
+    orders: Vec<Arc<[String]>>,
+    tags: HashMap<Arc<[String]>, i32>,
+}
+
+static KEYWORD_ORDERS: LazyLock<Mutex<KeywordOrders>> = LazyLock::new(Default::default);
+
+/// The tag of the object map converted from a keyword list with these keys, in order.
+pub fn keyword_list_tag(keys: Vec<String>) -> i32 {
+    if keys.is_sorted() {
+        return SORTED_KEYWORD_LIST;
+    }
+
+    let keys: Arc<[String]> = keys.into();
+    let mut keyword_orders = lock::lock(&KEYWORD_ORDERS);
+
+    if let Some(tag) = keyword_orders.tags.get(&keys) {
+        return *tag;
+    }
+
+    let index = keyword_orders.orders.len();
+
+    if index >= MAX_KEYWORD_ORDERS {
+        return SORTED_KEYWORD_LIST;
+    }
+
+    let tag = SORTED_KEYWORD_LIST - 1 - index as i32;
+
+    keyword_orders.orders.push(keys.clone());
+    keyword_orders.tags.insert(keys, tag);
+
+    tag
+}
+
+/// The keys, in order, of the keyword list an object map with this tag was converted from, or
+/// `None` if the map was not converted from a keyword list.
+pub fn keyword_list_order(tag: i32) -> Option<Arc<[String]>> {
+    match tag {
+        SORTED_KEYWORD_LIST => Some(Arc::new([])),
+        _ if tag < SORTED_KEYWORD_LIST => {
+            let index = (SORTED_KEYWORD_LIST - 1 - tag) as usize;
+
+            lock::lock(&KEYWORD_ORDERS).orders.get(index).cloned()
+        }
+        _ => None,
+    }
+}
+
+def_package! {
+    /// Package of the functions of the Elixir tuples passed to scripts.
+    pub CollectionsPackage(lib) {
+        lib.set_custom_type::<Tuple>("Tuple");
+        lib.set_iterable::<Tuple>();
+
+        combine_with_exported_module!(lib, "tuple", tuple_functions);
+    }
+}
+
+#[export_module]
+mod tuple_functions {
+    /// Return the element at a position, counting from the end if it is negative.
+    #[rhai_fn(index_get, return_raw, pure)]
+    pub fn get(tuple: &mut Tuple, index: INT) -> Result<Dynamic, Box<EvalAltResult>> {
+        let len = tuple.0.len();
+        let position = if index < 0 {
+            len.checked_sub(index.unsigned_abs() as usize)
+        } else {
+            Some(index as usize)
+        };
+
+        position
+            .and_then(|position| tuple.0.get(position))
+            .cloned()
+            .ok_or_else(|| EvalAltResult::ErrorArrayBounds(len, index, Position::NONE).into())
+    }
+
+    /// Return the number of elements.
+    #[rhai_fn(name = "len", get = "len", pure)]
+    pub fn len(tuple: &mut Tuple) -> INT {
+        tuple.0.len() as INT
+    }
+
+    /// Return the elements as an array.
+    #[rhai_fn(pure)]
+    pub fn to_array(tuple: &mut Tuple) -> Array {
+        tuple.0.clone()
+    }
+
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn to_string(tuple: &mut Tuple) -> String {
+        tuple.to_string()
+    }
+}
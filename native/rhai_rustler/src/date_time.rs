@@ -0,0 +1,505 @@
+use std::{
+    fmt,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rhai::{def_package, plugin::*, EvalAltResult, Position, FLOAT, INT};
+use rustler::{Atom, Encoder, Env, NifStruct, Term};
+
+use crate::error::ConversionError;
+
+mod atoms {
+    rustler::atoms! {
+        calendar_iso = "Elixir.Calendar.ISO"
+    }
+}
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+const MAX_YEAR: u64 = 292_000;
+
+/// A wall-clock time, converted from and to Elixir `DateTime` (in UTC) and `NaiveDateTime` structs.
+///
+/// Unlike Rhai timestamps, which are monotonic instants, wall-clock times can be compared with
+/// dates provided by the host and passed back to it.
+#[derive(Clone, Copy, Debug)]
+pub struct DateTime {
+    /// Microseconds since the Unix epoch, in UTC for Elixir `DateTime`s.
+    micros: i64,
+    /// Number of digits of the microseconds of the Elixir struct, kept so that values round-trip.
+    precision: u8,
+    /// Whether the time has no time zone, as an Elixir `NaiveDateTime`.
+    naive: bool,
+}
+
+impl DateTime {
+    pub fn now() -> Self {
+        let micros = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_micros() as i64,
+            Err(err) => -(err.duration().as_micros() as i64),
+        };
+
+        DateTime {
+            micros,
+            precision: 6,
+            naive: false,
+        }
+    }
+
+    /// The wall-clock time of a Rhai timestamp.
+    pub fn from_instant(instant: Instant) -> Self {
+        let now = Instant::now();
+        let DateTime { micros, .. } = DateTime::now();
+
+        let micros = if instant <= now {
+            micros - (now - instant).as_micros() as i64
+        } else {
+            micros + (instant - now).as_micros() as i64
+        };
+
+        DateTime {
+            micros,
+            precision: 6,
+            naive: false,
+        }
+    }
+
+    /// Add a number of microseconds, failing if it or the result is out of range.
+    fn checked_add(self, micros: Option<i64>) -> Result<Self, Box<EvalAltResult>> {
+        match micros.and_then(|micros| self.micros.checked_add(micros)) {
+            Some(micros) => Ok(DateTime { micros, ..self }),
+            None => Err(EvalAltResult::ErrorArithmetic(
+                "Date-time overflow".to_string(),
+                Position::NONE,
+            )
+            .into()),
+        }
+    }
+
+    /// The microseconds of this time and another, which must both be naive or both be in UTC, as
+    /// a naive time has no time zone to order it with a time in UTC.
+    fn comparable(self, other: Self) -> Result<(i64, i64), Box<EvalAltResult>> {
+        if self.naive != other.naive {
+            return Err(EvalAltResult::ErrorRuntime(
+                "Cannot compare a naive date-time with a date-time in UTC".into(),
+                Position::NONE,
+            )
+            .into());
+        }
+
+        Ok((self.micros, other.micros))
+    }
+
+    /// Decode an Elixir `DateTime` or `NaiveDateTime` struct, if the term is one.
+    pub fn from_term(term: Term) -> Option<Result<Self, ConversionError>> {
+        if let Ok(date_time) = term.decode::<ElixirDateTime>() {
+            Some(date_time.try_into())
+        } else if let Ok(date_time) = term.decode::<ElixirNaiveDateTime>() {
+            Some(date_time.try_into())
+        } else {
+            None
+        }
+    }
+
+    /// The date and time fields, as Elixir structs hold them.
+    fn fields(&self) -> Fields {
+        let days = self.micros.div_euclid(MICROS_PER_DAY);
+        let micros = self.micros.rem_euclid(MICROS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        let seconds = micros / MICROS_PER_SECOND;
+
+        Fields {
+            year,
+            month,
+            day,
+            hour: (seconds / 3600) as u32,
+            minute: (seconds / 60 % 60) as u32,
+            second: (seconds % 60) as u32,
+            microsecond: (micros % MICROS_PER_SECOND) as u32,
+        }
+    }
+
+    fn from_fields(
+        fields: Fields,
+        offset: i64,
+        precision: u8,
+        naive: bool,
+    ) -> Result<Self, ConversionError> {
+        let seconds = (i64::from(fields.hour) * 3600
+            + i64::from(fields.minute) * 60
+            + i64::from(fields.second))
+        .checked_sub(offset);
+
+        // Microseconds since the Unix epoch only fit into an integer for about 292,000 years.
+        (fields.year.unsigned_abs() <= MAX_YEAR)
+            .then(|| days_from_civil(fields.year, fields.month, fields.day))
+            .and_then(|days| days.checked_mul(MICROS_PER_DAY))
+            .zip(seconds.and_then(|seconds| seconds.checked_mul(MICROS_PER_SECOND)))
+            .and_then(|(days, seconds)| days.checked_add(seconds))
+            .and_then(|micros| micros.checked_add(i64::from(fields.microsecond)))
+            .map(|micros| DateTime {
+                micros,
+                precision,
+                naive,
+            })
+            .ok_or_else(|| {
+                ConversionError::new(format!(
+                    "Cannot convert the date-time of year {}, it is out of range",
+                    fields.year
+                ))
+            })
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Format as ISO 8601, like Elixir does.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields = self.fields();
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            fields.year, fields.month, fields.day, fields.hour, fields.minute, fields.second
+        )?;
+
+        if self.precision > 0 {
+            let fraction = format!("{:06}", fields.microsecond);
+            write!(f, ".{}", &fraction[..usize::from(self.precision.min(6))])?;
+        }
+
+        if self.naive {
+            Ok(())
+        } else {
+            write!(f, "Z")
+        }
+    }
+}
+
+struct Fields {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    microsecond: u32,
+}
+
+/// Days since the Unix epoch of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Date of the proleptic Gregorian calendar of a number of days since the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[derive(NifStruct)]
+#[module = "DateTime"]
+struct ElixirDateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    microsecond: (u32, u8),
+    utc_offset: i64,
+    std_offset: i64,
+    time_zone: String,
+    zone_abbr: String,
+    calendar: Atom,
+}
+
+#[derive(NifStruct)]
+#[module = "NaiveDateTime"]
+struct ElixirNaiveDateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    microsecond: (u32, u8),
+    calendar: Atom,
+}
+
+impl TryFrom<ElixirDateTime> for DateTime {
+    type Error = ConversionError;
+
+    fn try_from(date_time: ElixirDateTime) -> Result<Self, Self::Error> {
+        check_calendar(date_time.calendar)?;
+
+        let (microsecond, precision) = date_time.microsecond;
+        let fields = Fields {
+            year: date_time.year,
+            month: date_time.month,
+            day: date_time.day,
+            hour: date_time.hour,
+            minute: date_time.minute,
+            second: date_time.second,
+            microsecond,
+        };
+        let offset = date_time.utc_offset.saturating_add(date_time.std_offset);
+
+        DateTime::from_fields(fields, offset, precision, false)
+    }
+}
+
+impl TryFrom<ElixirNaiveDateTime> for DateTime {
+    type Error = ConversionError;
+
+    fn try_from(date_time: ElixirNaiveDateTime) -> Result<Self, Self::Error> {
+        check_calendar(date_time.calendar)?;
+
+        let (microsecond, precision) = date_time.microsecond;
+        let fields = Fields {
+            year: date_time.year,
+            month: date_time.month,
+            day: date_time.day,
+            hour: date_time.hour,
+            minute: date_time.minute,
+            second: date_time.second,
+            microsecond,
+        };
+
+        DateTime::from_fields(fields, 0, precision, true)
+    }
+}
+
+fn check_calendar(calendar: Atom) -> Result<(), ConversionError> {
+    if calendar == atoms::calendar_iso() {
+        Ok(())
+    } else {
+        Err(ConversionError::new(
+            "Cannot convert a date-time of a calendar other than Calendar.ISO".to_string(),
+        ))
+    }
+}
+
+impl Encoder for DateTime {
+    /// Encode as a `NaiveDateTime` if the time has no time zone, or else as a `DateTime` in UTC.
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let fields = self.fields();
+        let microsecond = (fields.microsecond, self.precision);
+
+        if self.naive {
+            ElixirNaiveDateTime {
+                year: fields.year,
+                month: fields.month,
+                day: fields.day,
+                hour: fields.hour,
+                minute: fields.minute,
+                second: fields.second,
+                microsecond,
+                calendar: atoms::calendar_iso(),
+            }
+            .encode(env)
+        } else {
+            ElixirDateTime {
+                year: fields.year,
+                month: fields.month,
+                day: fields.day,
+                hour: fields.hour,
+                minute: fields.minute,
+                second: fields.second,
+                microsecond,
+                utc_offset: 0,
+                std_offset: 0,
+                time_zone: "Etc/UTC".to_string(),
+                zone_abbr: "UTC".to_string(),
+                calendar: atoms::calendar_iso(),
+            }
+            .encode(env)
+        }
+    }
+}
+
+def_package! {
+    /// Package of wall-clock time functions.
+    pub DateTimePackage(lib) {
+        lib.set_custom_type::<DateTime>("DateTime");
+
+        combine_with_exported_module!(lib, "date_time", date_time_functions);
+    }
+}
+
+#[export_module]
+mod date_time_functions {
+    /// Return the current wall-clock time, in UTC.
+    #[rhai_fn(volatile)]
+    pub fn now() -> DateTime {
+        DateTime::now()
+    }
+
+    /// Return the wall-clock time, in UTC, of a number of seconds since the Unix epoch.
+    #[rhai_fn(return_raw)]
+    pub fn from_unix(seconds: INT) -> Result<DateTime, Box<EvalAltResult>> {
+        let epoch = DateTime {
+            micros: 0,
+            precision: 0,
+            naive: false,
+        };
+
+        epoch.checked_add(seconds.checked_mul(MICROS_PER_SECOND))
+    }
+
+    /// Return the wall-clock time of a timestamp, in UTC.
+    pub fn to_date_time(timestamp: Instant) -> DateTime {
+        DateTime::from_instant(timestamp)
+    }
+
+    /// Return the number of seconds since the Unix epoch.
+    #[rhai_fn(get = "unix", pure)]
+    pub fn unix(date_time: &mut DateTime) -> INT {
+        date_time.micros.div_euclid(MICROS_PER_SECOND)
+    }
+
+    /// Return the number of seconds between the wall-clock time and now, negative if it is in
+    /// the future.
+    #[rhai_fn(get = "elapsed", pure)]
+    pub fn elapsed(date_time: &mut DateTime) -> FLOAT {
+        seconds_between(DateTime::now().micros, date_time.micros)
+    }
+
+    #[rhai_fn(get = "year", pure)]
+    pub fn year(date_time: &mut DateTime) -> INT {
+        date_time.fields().year
+    }
+
+    #[rhai_fn(get = "month", pure)]
+    pub fn month(date_time: &mut DateTime) -> INT {
+        date_time.fields().month.into()
+    }
+
+    #[rhai_fn(get = "day", pure)]
+    pub fn day(date_time: &mut DateTime) -> INT {
+        date_time.fields().day.into()
+    }
+
+    #[rhai_fn(get = "hour", pure)]
+    pub fn hour(date_time: &mut DateTime) -> INT {
+        date_time.fields().hour.into()
+    }
+
+    #[rhai_fn(get = "minute", pure)]
+    pub fn minute(date_time: &mut DateTime) -> INT {
+        date_time.fields().minute.into()
+    }
+
+    #[rhai_fn(get = "second", pure)]
+    pub fn second(date_time: &mut DateTime) -> INT {
+        date_time.fields().second.into()
+    }
+
+    /// Add a number of seconds to the wall-clock time.
+    #[rhai_fn(name = "+", return_raw)]
+    pub fn add(date_time: DateTime, seconds: INT) -> Result<DateTime, Box<EvalAltResult>> {
+        date_time.checked_add(seconds.checked_mul(MICROS_PER_SECOND))
+    }
+
+    #[rhai_fn(name = "+", return_raw)]
+    pub fn add_float(date_time: DateTime, seconds: FLOAT) -> Result<DateTime, Box<EvalAltResult>> {
+        // Fractions of seconds are kept, so they are not hidden by a lower precision.
+        let date_time = DateTime {
+            precision: 6,
+            ..date_time
+        };
+
+        date_time.checked_add(float_micros(seconds))
+    }
+
+    /// Subtract a number of seconds from the wall-clock time.
+    #[rhai_fn(name = "-", return_raw)]
+    pub fn subtract(date_time: DateTime, seconds: INT) -> Result<DateTime, Box<EvalAltResult>> {
+        date_time.checked_add(seconds.checked_mul(-MICROS_PER_SECOND))
+    }
+
+    #[rhai_fn(name = "-", return_raw)]
+    pub fn subtract_float(
+        date_time: DateTime,
+        seconds: FLOAT,
+    ) -> Result<DateTime, Box<EvalAltResult>> {
+        add_float(date_time, -seconds)
+    }
+
+    /// Return the number of seconds between two wall-clock times, which must both be naive or
+    /// both be in UTC.
+    #[rhai_fn(name = "-", return_raw)]
+    pub fn diff(date_time: DateTime, other: DateTime) -> Result<FLOAT, Box<EvalAltResult>> {
+        let (a, b) = date_time.comparable(other)?;
+
+        Ok(seconds_between(a, b))
+    }
+
+    /// A naive time is never equal to a time in UTC.
+    #[rhai_fn(name = "==")]
+    pub fn eq(date_time: DateTime, other: DateTime) -> bool {
+        date_time.naive == other.naive && date_time.micros == other.micros
+    }
+
+    #[rhai_fn(name = "!=")]
+    pub fn ne(date_time: DateTime, other: DateTime) -> bool {
+        !eq(date_time, other)
+    }
+
+    /// Ordering a naive time and a time in UTC is an error.
+    #[rhai_fn(name = "<", return_raw)]
+    pub fn lt(date_time: DateTime, other: DateTime) -> Result<bool, Box<EvalAltResult>> {
+        date_time.comparable(other).map(|(a, b)| a < b)
+    }
+
+    #[rhai_fn(name = "<=", return_raw)]
+    pub fn le(date_time: DateTime, other: DateTime) -> Result<bool, Box<EvalAltResult>> {
+        date_time.comparable(other).map(|(a, b)| a <= b)
+    }
+
+    #[rhai_fn(name = ">", return_raw)]
+    pub fn gt(date_time: DateTime, other: DateTime) -> Result<bool, Box<EvalAltResult>> {
+        date_time.comparable(other).map(|(a, b)| a > b)
+    }
+
+    #[rhai_fn(name = ">=", return_raw)]
+    pub fn ge(date_time: DateTime, other: DateTime) -> Result<bool, Box<EvalAltResult>> {
+        date_time.comparable(other).map(|(a, b)| a >= b)
+    }
+
+    /// Format the wall-clock time as ISO 8601.
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn to_string(date_time: &mut DateTime) -> String {
+        date_time.to_string()
+    }
+}
+
+/// Number of seconds between two times in microseconds.
+fn seconds_between(micros: i64, other: i64) -> FLOAT {
+    (micros as FLOAT - other as FLOAT) / MICROS_PER_SECOND as FLOAT
+}
+
+/// Microseconds of a number of seconds, if they fit into an integer.
+fn float_micros(seconds: FLOAT) -> Option<i64> {
+    let micros = seconds * MICROS_PER_SECOND as FLOAT;
+
+    (micros.is_finite() && micros >= i64::MIN as FLOAT && micros < i64::MAX as FLOAT)
+        .then_some(micros as i64)
+}
@@ -0,0 +1,73 @@
+use rust_decimal::Decimal;
+use rustler::{Encoder, Env, NifStruct, Term};
+
+use crate::error::ConversionError;
+
+/// Elixir `Decimal` struct, whose coefficient is an integer, `:NaN` or `:inf`.
+#[derive(NifStruct)]
+#[module = "Decimal"]
+struct ElixirDecimal<'a> {
+    sign: i8,
+    coef: Term<'a>,
+    exp: i64,
+}
+
+/// Decode an Elixir `Decimal` struct, if the term is one.
+pub fn from_term(term: Term) -> Option<Result<Decimal, ConversionError>> {
+    let decimal = term.decode::<ElixirDecimal>().ok()?;
+
+    Some(to_decimal(&decimal).ok_or_else(|| {
+        ConversionError::new(format!(
+            "Cannot convert decimal {:?}, it does not fit into a Rhai decimal",
+            term
+        ))
+    }))
+}
+
+fn to_decimal(decimal: &ElixirDecimal) -> Option<Decimal> {
+    let coef = decimal.coef.decode::<i128>().ok()?;
+    let coef = if decimal.sign < 0 {
+        coef.checked_neg()?
+    } else {
+        coef
+    };
+
+    // Rhai decimals have a scale but no exponent, so positive exponents are multiplied out.
+    if decimal.exp >= 0 {
+        let exp = u32::try_from(decimal.exp).ok()?;
+        let coef = 10i128
+            .checked_pow(exp)
+            .and_then(|pow| coef.checked_mul(pow))?;
+
+        Decimal::try_from_i128_with_scale(coef, 0).ok()
+    } else {
+        let scale = u32::try_from(decimal.exp.unsigned_abs()).ok()?;
+
+        Decimal::try_from_i128_with_scale(coef, scale).ok()
+    }
+}
+
+/// Convert an integer that does not fit into a Rhai integer.
+pub fn from_integer(term: Term) -> Result<Decimal, ConversionError> {
+    term.decode::<i128>()
+        .ok()
+        .and_then(|int| Decimal::try_from_i128_with_scale(int, 0).ok())
+        .ok_or_else(|| {
+            ConversionError::new(format!(
+                "Cannot convert integer {:?}, it does not fit into a Rhai decimal",
+                term
+            ))
+        })
+}
+
+/// Encode as an Elixir `Decimal` struct.
+pub fn encode(env: Env, decimal: Decimal) -> Term {
+    let mantissa = decimal.mantissa();
+
+    ElixirDecimal {
+        sign: if decimal.is_sign_negative() { -1 } else { 1 },
+        coef: mantissa.abs().encode(env),
+        exp: -i64::from(decimal.scale()),
+    }
+    .encode(env)
+}
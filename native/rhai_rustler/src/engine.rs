@@ -1,23 +1,50 @@
-use std::sync::Mutex;
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
+};
 
 use rhai::{
-    module_resolvers::{FileModuleResolver, ModuleResolversCollection},
-    packages::Package as RhaiPackage,
-    Dynamic, Engine,
+    module_resolvers::ModuleResolversCollection, packages::Package as RhaiPackage, Dynamic, Engine,
+    EvalAltResult, Module, Scope, AST,
 };
 
 use rhai_dylib::loader::{libloading::Libloading, Loader};
 use rhai_dylib::module_resolvers::libloading::DylibModuleResolver;
 
-use rustler::{Env, NifUnitEnum, Resource, ResourceArc, Term};
+use rustler::{
+    Encoder, Env, LocalPid, NifResult, NifTaggedEnum, NifUnitEnum, OwnedEnv, Resource, ResourceArc,
+    Term,
+};
 
 use crate::{
     ast::ASTResource,
-    error::RhaiRustlerError,
+    callback,
+    collections::CollectionsPackage,
+    date_time::DateTimePackage,
+    error::{catch_panic, BusyError, RhaiRustlerError},
+    eval_options::{on_progress, time_left, EvalOptions},
+    fun, lock,
+    module_resolver::{
+        FileModuleResolverOptions, ModuleAccess, ProcessModuleResolverResource,
+        RestrictedModuleResolver, SandboxedFileModuleResolver, SharedProcessModuleResolver,
+        SharedStaticModuleResolver, StaticModuleResolverResource,
+    },
+    opaque::OpaquePackage,
+    output::{set_output, OutputTarget},
+    pool,
     scope::ScopeResource,
-    types::{from_dynamic, to_dynamic},
+    types::{from_dynamic, from_dynamic_with, to_dynamic, to_dynamic_with, Tuples},
 };
 
+mod atoms {
+    rustler::atoms! {
+        rhai_call
+    }
+}
+
 #[cfg(target_os = "linux")]
 const DYLIB_EXTENSION: &str = "so";
 #[cfg(target_os = "macos")]
@@ -26,52 +53,93 @@ const DYLIB_EXTENSION: &str = "dylib";
 const DYLIB_EXTENSION: &str = "dll";
 
 pub struct EngineResource {
-    pub engine: Mutex<Engine>,
+    pub engine: RwLock<Engine>,
 }
 
 #[rustler::resource_impl]
 impl Resource for EngineResource {}
 
+impl EngineResource {
+    /// Evaluations only need a shared reference to the engine, so any number of them can run at once.
+    pub fn read(&self) -> RwLockReadGuard<'_, Engine> {
+        lock::read(&self.engine)
+    }
+
+    /// Configuring the engine fails while it is evaluating a script.
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, Engine>, BusyError> {
+        lock::try_write(&self.engine, "engine")
+    }
+}
+
 #[rustler::nif]
 fn engine_new() -> ResourceArc<EngineResource> {
-    let engine = Engine::new();
+    let mut engine = Engine::new();
+    CollectionsPackage::new().register_into_engine(&mut engine);
+    DateTimePackage::new().register_into_engine(&mut engine);
+    OpaquePackage::new().register_into_engine(&mut engine);
+    engine.on_progress(on_progress);
+    set_output(&mut engine, OutputTarget::Stdout);
+    fun::register(&mut engine);
 
     ResourceArc::new(EngineResource {
-        engine: Mutex::new(engine),
+        engine: RwLock::new(engine),
     })
 }
 
 #[rustler::nif]
 fn engine_new_raw() -> ResourceArc<EngineResource> {
-    let engine = Engine::new_raw();
+    let mut engine = Engine::new_raw();
+    engine.on_progress(on_progress);
+    set_output(&mut engine, OutputTarget::Discard);
+    fun::register(&mut engine);
 
     ResourceArc::new(EngineResource {
-        engine: Mutex::new(engine),
+        engine: RwLock::new(engine),
     })
 }
 
-#[derive(NifUnitEnum)]
+#[derive(NifTaggedEnum)]
 enum ModuleResolver {
-    File,
+    File(FileModuleResolverOptions),
     Dylib,
+    Static(ResourceArc<StaticModuleResolverResource>),
+    Process(ResourceArc<ProcessModuleResolverResource>),
 }
 
 #[rustler::nif]
 fn engine_set_module_resolvers(
     resource: ResourceArc<EngineResource>,
     module_resolvers: Vec<ModuleResolver>,
-) {
-    let mut engine = resource.engine.try_lock().unwrap();
+    access: ModuleAccess,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
     let mut resolvers_collection = ModuleResolversCollection::new();
 
     for module_resolver in module_resolvers {
         match module_resolver {
-            ModuleResolver::File => resolvers_collection.push(FileModuleResolver::new()),
+            ModuleResolver::File(options) => {
+                resolvers_collection.push(SandboxedFileModuleResolver::new(options))
+            }
             ModuleResolver::Dylib => resolvers_collection.push(DylibModuleResolver::new()),
+            ModuleResolver::Static(resource) => {
+                resolvers_collection.push(SharedStaticModuleResolver(resource))
+            }
+            ModuleResolver::Process(resource) => {
+                resolvers_collection.push(SharedProcessModuleResolver(resource))
+            }
         };
     }
 
-    engine.set_module_resolver(resolvers_collection);
+    if access.is_restricted() {
+        engine.set_module_resolver(RestrictedModuleResolver {
+            resolvers: resolvers_collection,
+            access,
+        });
+    } else {
+        engine.set_module_resolver(resolvers_collection);
+    }
+
+    Ok(())
 }
 
 #[rustler::nif]
@@ -79,14 +147,16 @@ fn engine_register_global_module(
     resource: ResourceArc<EngineResource>,
     path: String,
 ) -> Result<(), RhaiRustlerError> {
-    let mut engine = resource.engine.try_lock().unwrap();
-    let mut loader = Libloading::new();
+    catch_panic(|| {
+        let mut engine = resource.write()?;
+        let mut loader = Libloading::new();
 
-    let path = format!("{}.{}", path, DYLIB_EXTENSION);
+        let path = format!("{}.{}", path, DYLIB_EXTENSION);
 
-    engine.register_global_module(loader.load(path)?);
+        engine.register_global_module(loader.load(path)?);
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif]
@@ -95,14 +165,86 @@ fn engine_register_static_module(
     namespace: String,
     path: String,
 ) -> Result<(), RhaiRustlerError> {
-    let mut engine = resource.engine.try_lock().unwrap();
-    let mut loader = Libloading::new();
+    catch_panic(|| {
+        let mut engine = resource.write()?;
+        let mut loader = Libloading::new();
 
-    let path = format!("{}.{}", path, DYLIB_EXTENSION);
+        let path = format!("{}.{}", path, DYLIB_EXTENSION);
 
-    engine.register_static_module(namespace, loader.load(path)?);
+        engine.register_static_module(namespace, loader.load(path)?);
 
-    Ok(())
+        Ok(())
+    })
+}
+
+/// A Rhai script evaluated into a module.
+#[derive(NifTaggedEnum)]
+enum ScriptModule {
+    Ast(ResourceArc<ASTResource>),
+    Source(String),
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn engine_register_script_module(
+    resource: ResourceArc<EngineResource>,
+    namespace: Option<String>,
+    script: ScriptModule,
+    options: EvalOptions,
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        // The module is evaluated before locking the engine for writing, as evaluating it needs the engine.
+        let module = {
+            let engine = resource.read();
+
+            options.run(|| -> Result<Module, RhaiRustlerError> {
+                let module = match script {
+                    ScriptModule::Ast(ast_resource) => {
+                        Module::eval_ast_as_new(Scope::new(), &ast_resource.read(), &engine)?
+                    }
+                    ScriptModule::Source(source) => {
+                        Module::eval_ast_as_new(Scope::new(), &engine.compile(source)?, &engine)?
+                    }
+                };
+
+                Ok(module)
+            })?
+        };
+
+        let mut engine = resource.write()?;
+
+        match namespace {
+            Some(namespace) => engine.register_static_module(namespace, module.into()),
+            None => engine.register_global_module(module.into()),
+        };
+
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn engine_register_constants_module<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    namespace: String,
+    constants: HashMap<String, Term<'a>>,
+    tuples: Tuples,
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        let mut module = Module::new();
+
+        for (name, value) in constants {
+            let value =
+                to_dynamic_with(env, &value, tuples).map_err(|err| err.within(name.as_str()))?;
+
+            module.set_var(name, value);
+        }
+
+        let mut engine = resource.write()?;
+
+        engine.register_static_module(namespace, module.into());
+
+        Ok(())
+    })
 }
 
 #[rustler::nif]
@@ -111,12 +253,68 @@ fn engine_register_custom_operator(
     keyword: &str,
     precedence: u8,
 ) -> Result<(), RhaiRustlerError> {
-    let mut engine = resource.engine.try_lock().unwrap();
+    catch_panic(|| {
+        let mut engine = resource.write()?;
+
+        match engine.register_custom_operator(keyword, precedence) {
+            Ok(_) => Ok(()),
+            Err(message) => Err(RhaiRustlerError::CustomOperator { message }),
+        }
+    })
+}
+
+#[rustler::nif]
+fn engine_register_fn(
+    resource: ResourceArc<EngineResource>,
+    name: String,
+    arity: usize,
+    pid: LocalPid,
+    timeout: u64,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
+    let timeout = Duration::from_millis(timeout);
+    let fn_name = name.clone();
+
+    engine.register_raw_fn(
+        name,
+        vec![TypeId::of::<Dynamic>(); arity],
+        move |context, args| {
+            let name = fn_name.clone();
+            let args: Vec<Dynamic> = args.iter_mut().map(|arg| std::mem::take(*arg)).collect();
+            // Do not wait for the reply past the deadline of the evaluation.
+            let call_timeout = time_left().map_or(timeout, |time_left| time_left.min(timeout));
+
+            callback::call(pid, Some(call_timeout), move |env, callback| {
+                let args: Vec<Term> = args
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, arg)| {
+                        from_dynamic(env, arg).map_err(|err| err.within(format!("args[{}]", i)))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Ok((atoms::rhai_call(), callback, name, args).encode(env))
+            })
+            .map_err(|err| err.into_eval_error(&fn_name, call_timeout, context.position()))
+        },
+    );
 
-    match engine.register_custom_operator(keyword, precedence) {
-        Ok(_) => Ok(()),
-        Err(message) => Err(RhaiRustlerError::CustomOperator { message }),
+    Ok(())
+}
+
+#[rustler::nif]
+fn engine_set_output(
+    resource: ResourceArc<EngineResource>,
+    pid: Option<LocalPid>,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
+
+    match pid {
+        Some(pid) => set_output(&mut engine, OutputTarget::Pid(pid)),
+        None => set_output(&mut engine, OutputTarget::Stdout),
     }
+
+    Ok(())
 }
 
 #[derive(NifUnitEnum)]
@@ -131,16 +329,22 @@ enum Package {
     BasicString,
     BasicTime,
     BitField,
+    Collections,
     Core,
+    DateTime,
     LanguageCore,
     Logic,
     MoreString,
+    Opaque,
     Standard,
 }
 
 #[rustler::nif]
-fn engine_register_package(resource: ResourceArc<EngineResource>, package: Package) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_register_package(
+    resource: ResourceArc<EngineResource>,
+    package: Package,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     match package {
         Package::Arithmetic => {
@@ -183,10 +387,18 @@ fn engine_register_package(resource: ResourceArc<EngineResource>, package: Packa
             let package = rhai::packages::BitFieldPackage::new();
             package.register_into_engine(&mut engine);
         }
+        Package::Collections => {
+            let package = CollectionsPackage::new();
+            package.register_into_engine(&mut engine);
+        }
         Package::Core => {
             let package = rhai::packages::CorePackage::new();
             package.register_into_engine(&mut engine);
         }
+        Package::DateTime => {
+            let package = DateTimePackage::new();
+            package.register_into_engine(&mut engine);
+        }
         Package::LanguageCore => {
             let package = rhai::packages::LanguageCorePackage::new();
             package.register_into_engine(&mut engine);
@@ -199,26 +411,48 @@ fn engine_register_package(resource: ResourceArc<EngineResource>, package: Packa
             let package = rhai::packages::MoreStringPackage::new();
             package.register_into_engine(&mut engine);
         }
+        Package::Opaque => {
+            let package = OpaquePackage::new();
+            package.register_into_engine(&mut engine);
+        }
         Package::Standard => {
             let package = rhai::packages::StandardPackage::new();
             package.register_into_engine(&mut engine);
         }
     };
+
+    Ok(())
+}
+
+/// Wrap a compiled AST in a resource, returning compilation errors as detailed error maps when
+/// `detailed_errors` is set.
+fn compile_ast<E>(
+    detailed_errors: bool,
+    compile: impl FnOnce() -> Result<AST, E>,
+) -> Result<ResourceArc<ASTResource>, RhaiRustlerError>
+where
+    E: Into<RhaiRustlerError>,
+{
+    match compile() {
+        Ok(ast) => Ok(ResourceArc::new(ASTResource {
+            ast: RwLock::new(ast),
+        })),
+        Err(err) if detailed_errors => Err(err.into().detailed(None)),
+        Err(err) => Err(err.into()),
+    }
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
 fn engine_compile(
     resource: ResourceArc<EngineResource>,
     script: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let ast = engine.compile(script)?;
+    catch_panic(|| {
+        let engine = resource.read();
 
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
-
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || engine.compile(script))
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -226,31 +460,29 @@ fn engine_compile_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     script: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let scope = scope_resource.scope.try_lock().unwrap();
-    let ast = engine.compile_with_scope(&scope, script)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let scope = scope_resource.read()?;
 
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
-
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || {
+            engine.compile_with_scope(&scope, script)
+        })
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
 fn engine_compile_expression(
     resource: ResourceArc<EngineResource>,
     expression: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let ast = engine.compile_expression(expression)?;
+    catch_panic(|| {
+        let engine = resource.read();
 
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
-
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || engine.compile_expression(expression))
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -258,31 +490,29 @@ fn engine_compile_expression_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     expression: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let scope = scope_resource.scope.try_lock().unwrap();
-    let ast = engine.compile_expression_with_scope(&scope, expression)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let scope = scope_resource.read()?;
 
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
-
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || {
+            engine.compile_expression_with_scope(&scope, expression)
+        })
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
 fn engine_compile_file(
     resource: ResourceArc<EngineResource>,
     path: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let ast = engine.compile_file(path.into())?;
+    catch_panic(|| {
+        let engine = resource.read();
 
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
-
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || engine.compile_file(path.into()))
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -290,16 +520,16 @@ fn engine_compile_file_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     path: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let scope = scope_resource.scope.try_lock().unwrap();
-    let ast = engine.compile_file_with_scope(&scope, path.into())?;
-
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
+    catch_panic(|| {
+        let engine = resource.read();
+        let scope = scope_resource.read()?;
 
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || {
+            engine.compile_file_with_scope(&scope, path.into())
+        })
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -307,16 +537,16 @@ fn engine_compile_into_self_contained(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     script: &str,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let scope = scope_resource.scope.try_lock().unwrap();
-    let ast = engine.compile_into_self_contained(&scope, script)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let scope = scope_resource.read()?;
 
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
-
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || {
+            engine.compile_into_self_contained(&scope, script)
+        })
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -324,16 +554,16 @@ fn engine_compile_scripts_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     scripts: Vec<String>,
+    detailed_errors: bool,
 ) -> Result<ResourceArc<ASTResource>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let scope = scope_resource.scope.try_lock().unwrap();
-    let ast = engine.compile_scripts_with_scope(&scope, scripts)?;
-
-    let ast_resource = ResourceArc::new(ASTResource {
-        ast: Mutex::new(ast),
-    });
+    catch_panic(|| {
+        let engine = resource.read();
+        let scope = scope_resource.read()?;
 
-    Ok(ast_resource)
+        compile_ast(detailed_errors, || {
+            engine.compile_scripts_with_scope(&scope, scripts)
+        })
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -341,10 +571,12 @@ fn engine_compact_script(
     resource: ResourceArc<EngineResource>,
     script: &str,
 ) -> Result<String, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let result = engine.compact_script(script)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let result = engine.compact_script(script)?;
 
-    Ok(result)
+        Ok(result)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -352,11 +584,91 @@ fn engine_eval<'a>(
     env: Env<'a>,
     resource: ResourceArc<EngineResource>,
     script: &str,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let result = engine.eval::<Dynamic>(script)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let (result, ast) = options.run(|| eval_script(&engine, &mut Scope::new(), script))?;
+
+        Ok(from_dynamic_with(
+            env,
+            result,
+            Some(&ast),
+            options.map_keys,
+        )?)
+    })
+}
+
+/// Compile and evaluate a script like `Engine::eval_with_scope`, keeping the AST so that function
+/// pointers to the functions it defines can be called later.
+fn eval_script(
+    engine: &Engine,
+    scope: &mut Scope,
+    script: &str,
+) -> Result<(Dynamic, AST), Box<EvalAltResult>> {
+    let ast = engine.compile_with_scope(scope, script)?;
+    let result = engine.eval_ast_with_scope::<Dynamic>(scope, &ast)?;
+
+    Ok((result, ast))
+}
 
-    Ok(from_dynamic(env, result))
+/// Run an evaluation on the thread pool and send `{ref, result}` to the calling process when it
+/// completes, where `result` is what the blocking NIF would have returned.
+///
+/// Captured output is sent along with the result as `{ref, {result, output}}`. `source` is the
+/// source of the evaluated AST, if any, reported by detailed errors.
+fn spawn_evaluation<'a, F>(
+    env: Env<'a>,
+    options: EvalOptions,
+    source: Option<String>,
+    eval: F,
+) -> Term<'a>
+where
+    F: for<'b> FnOnce(Env<'b>) -> Result<Term<'b>, RhaiRustlerError> + Send + 'static,
+{
+    let pid = env.pid();
+    let reference = env.make_ref();
+    let mut owned_env = OwnedEnv::new();
+    let saved_reference = owned_env.save(reference);
+
+    pool::spawn(move || {
+        // The result is dropped if the calling process exited in the meantime.
+        let _ = owned_env.send_and_clear(&pid, |env| {
+            let output = options.output.clone();
+            let encode = |result: Result<Term, RhaiRustlerError>| match &output {
+                Some(output) => (result, output.take()).encode(env),
+                None => result.encode(env),
+            };
+
+            // A panic outside of the evaluation, such as while encoding the result, is replied
+            // too, so that the caller is not left waiting.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                encode(options.run_with_source(source.as_deref(), || eval(env)))
+            }))
+            .unwrap_or_else(|payload| encode(Err(RhaiRustlerError::from_panic(payload))));
+
+            (saved_reference.load(env), result).encode(env)
+        });
+    });
+
+    reference.encode(env)
+}
+
+#[rustler::nif]
+fn engine_eval_async<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    script: String,
+    options: EvalOptions,
+) -> Term<'a> {
+    let map_keys = options.map_keys;
+
+    spawn_evaluation(env, options, None, move |env| {
+        let engine = resource.read();
+        let (result, ast) = eval_script(&engine, &mut Scope::new(), &script)?;
+
+        Ok(from_dynamic_with(env, result, Some(&ast), map_keys)?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -365,12 +677,20 @@ fn engine_eval_with_scope<'a>(
     engine_resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     script: &str,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = engine_resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
-    let result = engine.eval_with_scope::<Dynamic>(&mut scope, script)?;
-
-    Ok(from_dynamic(env, result))
+    catch_panic(|| {
+        let engine = engine_resource.read();
+        let mut scope = scope_resource.write()?;
+        let (result, ast) = options.run(|| eval_script(&engine, &mut scope, script))?;
+
+        Ok(from_dynamic_with(
+            env,
+            result,
+            Some(&ast),
+            options.map_keys,
+        )?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -378,13 +698,21 @@ fn engine_eval_ast(
     env: Env,
     engine_resource: ResourceArc<EngineResource>,
     ast_resource: ResourceArc<ASTResource>,
+    options: EvalOptions,
 ) -> Result<Term, RhaiRustlerError> {
-    let engine = engine_resource.engine.try_lock().unwrap();
-    let ast = ast_resource.ast.try_lock().unwrap();
-
-    let result = engine.eval_ast(&ast)?;
-
-    Ok(from_dynamic(env, result))
+    catch_panic(|| {
+        let engine = engine_resource.read();
+        let ast = ast_resource.read();
+
+        let result = options.run_with_source(ast.source(), || engine.eval_ast(&ast))?;
+
+        Ok(from_dynamic_with(
+            env,
+            result,
+            Some(&ast),
+            options.map_keys,
+        )?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -393,14 +721,24 @@ fn engine_eval_ast_with_scope(
     engine_resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     ast_resource: ResourceArc<ASTResource>,
+    options: EvalOptions,
 ) -> Result<Term, RhaiRustlerError> {
-    let engine = engine_resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
-    let ast = ast_resource.ast.try_lock().unwrap();
-
-    let result = engine.eval_ast_with_scope::<Dynamic>(&mut scope, &ast)?;
-
-    Ok(from_dynamic(env, result))
+    catch_panic(|| {
+        let engine = engine_resource.read();
+        let mut scope = scope_resource.write()?;
+        let ast = ast_resource.read();
+
+        let result = options.run_with_source(ast.source(), || {
+            engine.eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+        })?;
+
+        Ok(from_dynamic_with(
+            env,
+            result,
+            Some(&ast),
+            options.map_keys,
+        )?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -408,11 +746,14 @@ fn engine_eval_expression<'a>(
     env: Env<'a>,
     resource: ResourceArc<EngineResource>,
     expression: &str,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let result = engine.eval_expression::<Dynamic>(expression)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let result = options.run(|| engine.eval_expression::<Dynamic>(expression))?;
 
-    Ok(from_dynamic(env, result))
+        Ok(from_dynamic_with(env, result, None, options.map_keys)?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -421,12 +762,16 @@ fn engine_eval_expression_with_scope<'a>(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     expression: &str,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
-    let result = engine.eval_expression_with_scope::<Dynamic>(&mut scope, expression)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let mut scope = scope_resource.write()?;
+        let result =
+            options.run(|| engine.eval_expression_with_scope::<Dynamic>(&mut scope, expression))?;
 
-    Ok(from_dynamic(env, result))
+        Ok(from_dynamic_with(env, result, None, options.map_keys)?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -434,11 +779,14 @@ fn engine_eval_file<'a>(
     env: Env<'a>,
     resource: ResourceArc<EngineResource>,
     path: &str,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let result = engine.eval_file::<Dynamic>(path.into())?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let result = options.run(|| engine.eval_file::<Dynamic>(path.into()))?;
 
-    Ok(from_dynamic(env, result))
+        Ok(from_dynamic_with(env, result, None, options.map_keys)?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -447,20 +795,30 @@ fn engine_eval_file_with_scope<'a>(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     path: &str,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
-    let result = engine.eval_file_with_scope::<Dynamic>(&mut scope, path.into())?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let mut scope = scope_resource.write()?;
+        let result =
+            options.run(|| engine.eval_file_with_scope::<Dynamic>(&mut scope, path.into()))?;
 
-    Ok(from_dynamic(env, result))
+        Ok(from_dynamic_with(env, result, None, options.map_keys)?)
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
-fn engine_run(resource: ResourceArc<EngineResource>, script: &str) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    engine.run(script)?;
+fn engine_run(
+    resource: ResourceArc<EngineResource>,
+    script: &str,
+    options: EvalOptions,
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        let engine = resource.read();
+        options.run(|| engine.run(script))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -468,26 +826,32 @@ fn engine_run_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     script: &str,
+    options: EvalOptions,
 ) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
+    catch_panic(|| {
+        let engine = resource.read();
+        let mut scope = scope_resource.write()?;
 
-    engine.run_with_scope(&mut scope, script)?;
+        options.run(|| engine.run_with_scope(&mut scope, script))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
 fn engine_run_ast(
     resource: ResourceArc<EngineResource>,
     ast_resource: ResourceArc<ASTResource>,
+    options: EvalOptions,
 ) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let ast = ast_resource.ast.try_lock().unwrap();
+    catch_panic(|| {
+        let engine = resource.read();
+        let ast = ast_resource.read();
 
-    engine.run_ast(&ast)?;
+        options.run_with_source(ast.source(), || engine.run_ast(&ast))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -495,25 +859,31 @@ fn engine_run_ast_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     ast_resource: ResourceArc<ASTResource>,
+    options: EvalOptions,
 ) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
-    let ast = ast_resource.ast.try_lock().unwrap();
+    catch_panic(|| {
+        let engine = resource.read();
+        let mut scope = scope_resource.write()?;
+        let ast = ast_resource.read();
 
-    engine.run_ast_with_scope(&mut scope, &ast)?;
+        options.run_with_source(ast.source(), || engine.run_ast_with_scope(&mut scope, &ast))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
 fn engine_run_file(
     resource: ResourceArc<EngineResource>,
     path: &str,
+    options: EvalOptions,
 ) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    engine.run_file(path.into())?;
+    catch_panic(|| {
+        let engine = resource.read();
+        options.run(|| engine.run_file(path.into()))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -521,13 +891,16 @@ fn engine_run_file_with_scope(
     resource: ResourceArc<EngineResource>,
     scope_resource: ResourceArc<ScopeResource>,
     path: &str,
+    options: EvalOptions,
 ) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let mut scope = scope_resource.scope.try_lock().unwrap();
+    catch_panic(|| {
+        let engine = resource.read();
+        let mut scope = scope_resource.write()?;
 
-    engine.run_file_with_scope(&mut scope, path.into())?;
+        options.run(|| engine.run_file_with_scope(&mut scope, path.into()))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
@@ -538,168 +911,269 @@ fn engine_call_fn<'a>(
     ast: ResourceArc<ASTResource>,
     name: &str,
     args: Vec<Term<'a>>,
+    options: EvalOptions,
 ) -> Result<Term<'a>, RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    let mut scope = scope.scope.try_lock().unwrap();
-    let ast = ast.ast.try_lock().unwrap();
-
-    let args: Vec<Dynamic> = args.into_iter().map(|arg| to_dynamic(env, &arg)).collect();
-
-    let result = engine.call_fn(&mut scope, &ast, name, args)?;
+    catch_panic(|| {
+        let engine = resource.read();
+        let mut scope = scope.write()?;
+        let ast = ast.read();
+
+        let args: Vec<Dynamic> = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                to_dynamic_with(env, arg, options.tuples)
+                    .map_err(|err| err.within(format!("args[{}]", i)))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let result = options.run_with_source(ast.source(), || {
+            engine.call_fn(&mut scope, &ast, name, args)
+        })?;
+
+        Ok(from_dynamic_with(
+            env,
+            result,
+            Some(&ast),
+            options.map_keys,
+        )?)
+    })
+}
 
-    Ok(from_dynamic(env, result))
+#[rustler::nif]
+fn engine_call_fn_async<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    scope: ResourceArc<ScopeResource>,
+    ast: ResourceArc<ASTResource>,
+    name: String,
+    args: Vec<Term<'a>>,
+    options: EvalOptions,
+) -> Term<'a> {
+    // Arguments are converted right away, as terms cannot leave the calling process, but a
+    // conversion error is replied like any other error.
+    let args: Result<Vec<Dynamic>, _> = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            to_dynamic_with(env, arg, options.tuples)
+                .map_err(|err| err.within(format!("args[{}]", i)))
+        })
+        .collect();
+
+    let map_keys = options.map_keys;
+    let source = ast.read().source().map(str::to_string);
+
+    spawn_evaluation(env, options, source, move |env| {
+        let args = args?;
+        let engine = resource.read();
+        let mut scope = scope.write()?;
+        let ast = ast.read();
+        let result = engine.call_fn(&mut scope, &ast, &name, args)?;
+
+        Ok(from_dynamic_with(env, result, Some(&ast), map_keys)?)
+    })
 }
 
 #[rustler::nif]
-fn engine_set_allow_anonymous_fn(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_anonymous_fn(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_anonymous_fn(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_anonymous_fn(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_anonymous_fn()
 }
 
 #[rustler::nif]
-fn engine_set_allow_if_expression(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_if_expression(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_if_expression(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_if_expression(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_if_expression()
 }
 
 #[rustler::nif]
-fn engine_set_allow_loop_expressions(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_loop_expressions(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_loop_expressions(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_loop_expressions(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_loop_expressions()
 }
 
 #[rustler::nif]
-fn engine_set_allow_looping(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_looping(resource: ResourceArc<EngineResource>, enable: bool) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_looping(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_looping(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_looping()
 }
 
 #[rustler::nif]
-fn engine_set_allow_shadowing(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_shadowing(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_shadowing(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_shadowing(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_shadowing()
 }
 
 #[rustler::nif]
-fn engine_set_allow_statement_expression(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_statement_expression(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_statement_expression(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_statement_expression(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_statement_expression()
 }
 
 #[rustler::nif]
-fn engine_set_allow_switch_expression(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_allow_switch_expression(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_allow_switch_expression(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_allow_switch_expression(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.allow_switch_expression()
 }
 
 #[rustler::nif]
-fn engine_set_fail_on_invalid_map_property(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_fail_on_invalid_map_property(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_fail_on_invalid_map_property(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_fail_on_invalid_map_property(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.fail_on_invalid_map_property()
 }
 
 #[rustler::nif]
-fn engine_set_fast_operators(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_fast_operators(resource: ResourceArc<EngineResource>, enable: bool) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_fast_operators(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_fast_operators(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.fast_operators()
 }
 
 #[rustler::nif]
-fn engine_set_max_array_size(resource: ResourceArc<EngineResource>, max_size: usize) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_max_array_size(
+    resource: ResourceArc<EngineResource>,
+    max_size: usize,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_array_size(max_size);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_array_size(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_array_size()
 }
 
 #[rustler::nif]
-fn engine_set_max_call_levels(resource: ResourceArc<EngineResource>, levels: usize) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_max_call_levels(
+    resource: ResourceArc<EngineResource>,
+    levels: usize,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_call_levels(levels);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_call_levels(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_call_levels()
 }
@@ -709,92 +1183,116 @@ fn engine_set_max_expr_depths(
     resource: ResourceArc<EngineResource>,
     max_expr_depth: usize,
     max_function_expr_depth: usize,
-) {
-    let mut engine = resource.engine.try_lock().unwrap();
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_expr_depths(max_expr_depth, max_function_expr_depth);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_expr_depth(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_expr_depth()
 }
 
 #[rustler::nif]
 fn engine_max_function_expr_depth(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_function_expr_depth()
 }
 
 #[rustler::nif]
-fn engine_set_max_map_size(resource: ResourceArc<EngineResource>, max_size: usize) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_max_map_size(
+    resource: ResourceArc<EngineResource>,
+    max_size: usize,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_map_size(max_size);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_map_size(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_map_size()
 }
 
 #[rustler::nif]
-fn engine_set_max_modules(resource: ResourceArc<EngineResource>, modules: usize) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_max_modules(resource: ResourceArc<EngineResource>, modules: usize) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_modules(modules);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_modules(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_modules()
 }
 
 #[rustler::nif]
-fn engine_set_max_operations(resource: ResourceArc<EngineResource>, operations: u64) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_max_operations(
+    resource: ResourceArc<EngineResource>,
+    operations: u64,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_operations(operations);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_operations(resource: ResourceArc<EngineResource>) -> u64 {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_operations()
 }
 
 #[rustler::nif]
-fn engine_set_max_string_size(resource: ResourceArc<EngineResource>, max_len: usize) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_max_string_size(
+    resource: ResourceArc<EngineResource>,
+    max_len: usize,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_max_string_size(max_len);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_max_string_size(resource: ResourceArc<EngineResource>) -> usize {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.max_string_size()
 }
 
 #[rustler::nif]
-fn engine_set_strict_variables(resource: ResourceArc<EngineResource>, enable: bool) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_set_strict_variables(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_strict_variables(enable);
+
+    Ok(())
 }
 
 #[rustler::nif]
 fn engine_strict_variables(resource: ResourceArc<EngineResource>) -> bool {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.strict_variables()
 }
@@ -830,7 +1328,7 @@ impl From<OptimizationLevel> for rhai::OptimizationLevel {
 
 #[rustler::nif]
 fn engine_optimization_level(resource: ResourceArc<EngineResource>) -> OptimizationLevel {
-    let engine = resource.engine.try_lock().unwrap();
+    let engine = resource.read();
 
     engine.optimization_level().into()
 }
@@ -839,10 +1337,12 @@ fn engine_optimization_level(resource: ResourceArc<EngineResource>) -> Optimizat
 fn engine_set_optimization_level(
     resource: ResourceArc<EngineResource>,
     optimization_level: OptimizationLevel,
-) {
-    let mut engine = resource.engine.try_lock().unwrap();
+) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.set_optimization_level(optimization_level.into());
+
+    Ok(())
 }
 #[rustler::nif]
 fn engine_optimize_ast(
@@ -850,23 +1350,25 @@ fn engine_optimize_ast(
     scope_resource: ResourceArc<ScopeResource>,
     ast_resource: ResourceArc<ASTResource>,
     optimization_level: OptimizationLevel,
-) -> ResourceArc<ASTResource> {
-    let engine = resource.engine.try_lock().unwrap();
-    let scope = scope_resource.scope.try_lock().unwrap();
-    let ast = ast_resource.ast.try_lock().unwrap().clone();
+) -> NifResult<ResourceArc<ASTResource>> {
+    let engine = resource.read();
+    let scope = scope_resource.read()?;
+    let ast = ast_resource.read().clone();
 
     let result = engine.optimize_ast(&scope, ast, optimization_level.into());
 
-    ResourceArc::new(ASTResource {
-        ast: Mutex::new(result),
-    })
+    Ok(ResourceArc::new(ASTResource {
+        ast: RwLock::new(result),
+    }))
 }
 
 #[rustler::nif]
-fn engine_disable_symbol(resource: ResourceArc<EngineResource>, symbol: &str) {
-    let mut engine = resource.engine.try_lock().unwrap();
+fn engine_disable_symbol(resource: ResourceArc<EngineResource>, symbol: &str) -> NifResult<()> {
+    let mut engine = resource.write()?;
 
     engine.disable_symbol(symbol);
+
+    Ok(())
 }
 
 #[rustler::nif]
@@ -875,8 +1377,10 @@ fn engine_ensure_data_size_within_limits<'a>(
     resource: ResourceArc<EngineResource>,
     value: Term<'a>,
 ) -> Result<(), RhaiRustlerError> {
-    let engine = resource.engine.try_lock().unwrap();
-    engine.ensure_data_size_within_limits(&to_dynamic(env, &value))?;
+    catch_panic(|| {
+        let engine = resource.read();
+        engine.ensure_data_size_within_limits(&to_dynamic(env, &value)?)?;
 
-    Ok(())
+        Ok(())
+    })
 }
@@ -1,45 +1,82 @@
-use std::panic::RefUnwindSafe;
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe, RefUnwindSafe},
+};
 
 use thiserror::Error;
 
-use rhai::{EvalAltResult, ParseError};
-use rustler::{Encoder, Env, Term};
+use rhai::{Dynamic, EvalAltResult, LexError, ParseError, ParseErrorType, Position};
+use rustler::{Atom, Encoder, Env, NifMap, ResourceArc, Term};
+
+use crate::{cancel_token::CancelTokenResource, eval_options::Timeout, types::from_dynamic};
+
 mod atoms {
     rustler::atoms! {
         system,
         parsing,
         variable_exists,
         forbidden_variable,
-        variable_not_found,
-        property_not_found,
-        index_not_found,
-        function_not_found,
-        module_not_found,
         in_function_call,
         in_module,
-        unbound_this,
-        mismatch_data_type,
-        mismatch_output_type,
-        indexing_type,
-        array_bounds,
-        string_bounds,
-        bit_field_bounds,
-        for_atom = "for",
-        data_race,
         assignment_to_constant,
-        dot_expr,
-        arithmetic,
-        too_many_operations,
-        too_many_modules,
-        stack_overflow,
-        data_too_large,
         terminated,
-        custom_syntax,
         runtime,
-        non_pure_method_call_on_constant,
         scope_is_empty,
         cannot_update_value_of_constant,
-        custom_operator
+        custom_operator,
+        conversion,
+        timeout,
+        busy,
+        panic,
+        reason,
+        value,
+        nil,
+        unknown,
+        kind,
+        // parse errors
+        unexpected_eof,
+        bad_input,
+        unexpected_input,
+        unterminated_string,
+        string_too_long,
+        malformed_escape_sequence,
+        malformed_number,
+        malformed_char,
+        malformed_identifier,
+        improper_symbol,
+        unknown_operator,
+        missing_token,
+        missing_symbol,
+        malformed_call_expr,
+        malformed_index_expr,
+        malformed_in_expr,
+        malformed_capture,
+        duplicated_property,
+        duplicated_switch_case,
+        duplicated_variable,
+        wrong_switch_integer_case,
+        wrong_switch_default_case,
+        wrong_switch_case_condition,
+        property_expected,
+        variable_expected,
+        reserved,
+        mismatched_type,
+        expr_expected,
+        wrong_doc_comment,
+        wrong_fn_definition,
+        fn_duplicated_definition,
+        fn_missing_name,
+        fn_missing_params,
+        fn_duplicated_param,
+        fn_missing_body,
+        wrong_export,
+        assignment_to_invalid_lhs,
+        variable_undefined,
+        module_undefined,
+        expr_too_deep,
+        too_many_functions,
+        literal_too_large,
+        loop_break
     }
 }
 
@@ -51,6 +88,41 @@ pub enum ScopeError {
     ErrorCannotUpdateValueOfConstant,
 }
 
+/// A term or a Rhai value that cannot be converted, along with the path to the offending element.
+#[derive(Error, Debug)]
+#[error("{message}{}", if path.is_empty() { String::new() } else { format!(" at {}", path) })]
+pub struct ConversionError {
+    pub message: String,
+    pub path: String,
+}
+
+impl ConversionError {
+    pub fn new(message: String) -> Self {
+        ConversionError {
+            message,
+            path: String::new(),
+        }
+    }
+
+    /// Prefix the path with the location of the container holding the offending element.
+    pub fn within(mut self, location: impl Into<String>) -> Self {
+        let mut path: String = location.into();
+        path.push_str(&self.path);
+        self.path = path;
+        self
+    }
+}
+
+/// An import of a module path that is not allowed by the engine.
+#[derive(Error, Debug)]
+#[error("Module '{0}' is not allowed")]
+pub struct ModuleForbiddenError(pub String);
+
+/// A resource that cannot be locked because another NIF call is using it.
+#[derive(Error, Debug)]
+#[error("The {0} is being used by another process")]
+pub struct BusyError(pub &'static str);
+
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub struct EvaluationError(pub Box<EvalAltResult>);
@@ -73,58 +145,64 @@ pub enum RhaiRustlerError {
     Scope(#[from] ScopeError),
     #[error("Error when defining a custom operator: {message}.")]
     CustomOperator { message: String },
+    #[error("Error when converting a value: {0}.")]
+    Conversion(#[from] ConversionError),
+    #[error("Error when locking a resource: {0}.")]
+    Busy(#[from] BusyError),
+    #[error("Panic in native code: {0}.")]
+    Panic(String),
+    /// An error encoded as a map, with the source of the script that was evaluated, if known.
+    #[error("{0}")]
+    Detailed(Box<RhaiRustlerError>, Option<String>),
 }
 
-impl Encoder for RhaiRustlerError {
-    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+/// Run the body of a NIF, returning a panic as a `:panic` error instead of letting it unwind into
+/// the VM.
+pub fn catch_panic<T>(
+    body: impl FnOnce() -> Result<T, RhaiRustlerError>,
+) -> Result<T, RhaiRustlerError> {
+    panic::catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|payload| Err(RhaiRustlerError::from_panic(payload)))
+}
+
+/// NIFs that do not return a result raise the `{:busy, message}` error instead.
+impl From<BusyError> for rustler::Error {
+    fn from(err: BusyError) -> Self {
+        rustler::Error::RaiseTerm(Box::new(RhaiRustlerError::Busy(err)))
+    }
+}
+
+impl RhaiRustlerError {
+    /// Encode the error as a map with its position, source and call stack instead of a tuple.
+    ///
+    /// `source` is the source of the script that was evaluated, reported for the outermost error.
+    pub fn detailed(self, source: Option<&str>) -> Self {
         match self {
-            RhaiRustlerError::Evaluation(EvaluationError(err)) => {
-                let error_atom = match err.unwrap_inner() {
-                    EvalAltResult::ErrorSystem(_, _) => atoms::system(),
-                    EvalAltResult::ErrorParsing(_, _) => atoms::parsing(),
-                    EvalAltResult::ErrorVariableExists(_, _) => atoms::variable_exists(),
-                    EvalAltResult::ErrorForbiddenVariable(_, _) => atoms::forbidden_variable(),
-                    EvalAltResult::ErrorVariableNotFound(_, _) => atoms::variable_not_found(),
-                    EvalAltResult::ErrorPropertyNotFound(_, _) => atoms::property_not_found(),
-                    EvalAltResult::ErrorIndexNotFound(_, _) => atoms::index_not_found(),
-                    EvalAltResult::ErrorFunctionNotFound(_, _) => atoms::function_not_found(),
-                    EvalAltResult::ErrorModuleNotFound(_, _) => atoms::module_not_found(),
-                    EvalAltResult::ErrorInFunctionCall(_, _, _, _) => atoms::in_function_call(),
-                    EvalAltResult::ErrorInModule(_, _, _) => atoms::in_module(),
-                    EvalAltResult::ErrorUnboundThis(_) => atoms::unbound_this(),
-                    EvalAltResult::ErrorMismatchDataType(_, _, _) => atoms::mismatch_data_type(),
-                    EvalAltResult::ErrorMismatchOutputType(_, _, _) => {
-                        atoms::mismatch_output_type()
-                    }
-                    EvalAltResult::ErrorIndexingType(_, _) => atoms::indexing_type(),
-                    EvalAltResult::ErrorArrayBounds(_, _, _) => atoms::array_bounds(),
-                    EvalAltResult::ErrorStringBounds(_, _, _) => atoms::string_bounds(),
-                    EvalAltResult::ErrorBitFieldBounds(_, _, _) => atoms::bit_field_bounds(),
-                    EvalAltResult::ErrorFor(_) => atoms::for_atom(),
-                    EvalAltResult::ErrorDataRace(_, _) => atoms::data_race(),
-                    EvalAltResult::ErrorAssignmentToConstant(_, _) => {
-                        atoms::assignment_to_constant()
-                    }
-                    EvalAltResult::ErrorDotExpr(_, _) => atoms::dot_expr(),
-                    EvalAltResult::ErrorArithmetic(_, _) => atoms::arithmetic(),
-                    EvalAltResult::ErrorTooManyOperations(_) => atoms::too_many_operations(),
-                    EvalAltResult::ErrorTooManyModules(_) => atoms::too_many_modules(),
-                    EvalAltResult::ErrorStackOverflow(_) => atoms::stack_overflow(),
-                    EvalAltResult::ErrorDataTooLarge(_, _) => atoms::data_too_large(),
-                    EvalAltResult::ErrorTerminated(_, _) => atoms::terminated(),
-                    EvalAltResult::ErrorCustomSyntax(_, _, _) => atoms::custom_syntax(),
-                    EvalAltResult::ErrorRuntime(_, _) => atoms::runtime(),
-                    EvalAltResult::ErrorNonPureMethodCallOnConstant(_, _) => {
-                        atoms::non_pure_method_call_on_constant()
-                    }
-                    _ => panic!("Not an error"),
-                };
+            RhaiRustlerError::Detailed(..) => self,
+            err => RhaiRustlerError::Detailed(Box::new(err), source.map(str::to_string)),
+        }
+    }
 
-                make_reason_tuple(env, error_atom, err.to_string())
-            }
-            RhaiRustlerError::Parse(err) => {
-                make_reason_tuple(env, atoms::parsing(), err.to_string())
+    /// Error for a panic caught while running native code, from the payload of the panic.
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown panic".to_string(),
+            },
+        };
+
+        RhaiRustlerError::Panic(message)
+    }
+
+    /// The error atom and message of errors that do not come from an evaluation.
+    fn reason(&self, env: Env) -> (Atom, String) {
+        match self {
+            RhaiRustlerError::Evaluation(EvaluationError(err)) => {
+                (error_atom(env, err.unwrap_inner()), err.to_string())
             }
+            RhaiRustlerError::Parse(err) => (atoms::parsing(), err.to_string()),
             RhaiRustlerError::Scope(err) => {
                 let error_atom = match err {
                     ScopeError::ErrorScopeIsEmpty => atoms::scope_is_empty(),
@@ -133,15 +211,379 @@ impl Encoder for RhaiRustlerError {
                     }
                 };
 
-                make_reason_tuple(env, error_atom, err.to_string())
+                (error_atom, err.to_string())
             }
             RhaiRustlerError::CustomOperator { message } => {
-                make_reason_tuple(env, atoms::custom_operator(), message.to_owned())
+                (atoms::custom_operator(), message.to_owned())
+            }
+            RhaiRustlerError::Conversion(err) => (atoms::conversion(), err.to_string()),
+            RhaiRustlerError::Busy(err) => (atoms::busy(), err.to_string()),
+            RhaiRustlerError::Panic(message) => (atoms::panic(), message.to_owned()),
+            RhaiRustlerError::Detailed(err, _) => err.reason(env),
+        }
+    }
+}
+
+impl Encoder for RhaiRustlerError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            RhaiRustlerError::Evaluation(EvaluationError(err)) => {
+                if let EvalAltResult::ErrorTerminated(value, _) = err.unwrap_inner() {
+                    if let Some(cancel_token) =
+                        value.read_lock::<ResourceArc<CancelTokenResource>>()
+                    {
+                        return (
+                            atoms::terminated(),
+                            err.to_string(),
+                            cancel_token.reason(env),
+                        )
+                            .encode(env);
+                    }
+
+                    if let Some(timeout) = value.read_lock::<Timeout>() {
+                        return make_reason_tuple(env, atoms::timeout(), timeout_message(&timeout));
+                    }
+                }
+
+                if let EvalAltResult::ErrorRuntime(value, _) = err.unwrap_inner() {
+                    return (atoms::runtime(), err.to_string(), thrown_value(env, value))
+                        .encode(env);
+                }
+
+                let (error_atom, message) = self.reason(env);
+
+                make_reason_tuple(env, error_atom, message)
+            }
+            RhaiRustlerError::Detailed(err, source) => match err.as_ref() {
+                RhaiRustlerError::Evaluation(EvaluationError(err)) => {
+                    encode_evaluation_error(env, err, source.as_deref())
+                }
+                RhaiRustlerError::Parse(err) => {
+                    encode_parse_error(env, err.err_type(), err.position(), source.as_deref())
+                }
+                err => {
+                    let (error_atom, message) = err.reason(env);
+
+                    ErrorDetails {
+                        r#type: error_atom,
+                        message,
+                        ..ErrorDetails::at(Position::NONE, None)
+                    }
+                    .encode(env)
+                }
+            },
+            err => {
+                let (error_atom, message) = err.reason(env);
+
+                make_reason_tuple(env, error_atom, message)
             }
         }
     }
 }
 
-fn make_reason_tuple(env: Env, atom: rustler::types::atom::Atom, err_str: String) -> Term {
+/// Detailed error, encoded as a map.
+#[derive(NifMap)]
+struct ErrorDetails<'a> {
+    r#type: Atom,
+    message: String,
+    line: Option<usize>,
+    column: Option<usize>,
+    /// Source of the script the position refers to, if known.
+    source: Option<String>,
+    /// Names of the script functions being executed, outermost first.
+    function_stack: Vec<String>,
+    /// The error wrapped by an `:in_function_call` or `:in_module` error.
+    inner: Option<Term<'a>>,
+}
+
+impl ErrorDetails<'_> {
+    fn at(position: Position, source: Option<&str>) -> Self {
+        ErrorDetails {
+            r#type: atoms::system(),
+            message: String::new(),
+            line: position.line(),
+            column: position.position(),
+            source: source.map(str::to_string),
+            function_stack: Vec::new(),
+            inner: None,
+        }
+    }
+}
+
+/// Encode a parse error as a detailed error map, with the kind of the error under `:kind`.
+fn encode_parse_error<'a>(
+    env: Env<'a>,
+    err_type: &ParseErrorType,
+    position: Position,
+    source: Option<&str>,
+) -> Term<'a> {
+    let details = ErrorDetails {
+        r#type: atoms::parsing(),
+        message: err_type.to_string(),
+        ..ErrorDetails::at(position, source)
+    }
+    .encode(env);
+
+    details
+        .map_put(atoms::kind(), parse_error_atom(err_type))
+        .unwrap_or(details)
+}
+
+/// Encode an evaluation error as a map, following the chain of function calls and modules.
+///
+/// `source` is the source of the script the error was raised in, if known.
+fn encode_evaluation_error<'a>(
+    env: Env<'a>,
+    err: &EvalAltResult,
+    source: Option<&str>,
+) -> Term<'a> {
+    let mut function_stack = Vec::new();
+    let mut frame = err;
+    loop {
+        match frame {
+            EvalAltResult::ErrorInFunctionCall(name, _, inner, _) => {
+                function_stack.push(name.to_string());
+                frame = inner;
+            }
+            EvalAltResult::ErrorInModule(_, inner, _) => frame = inner,
+            _ => break,
+        }
+    }
+
+    let details = ErrorDetails {
+        function_stack,
+        ..ErrorDetails::at(err.position(), source)
+    };
+
+    match err {
+        EvalAltResult::ErrorInFunctionCall(name, fn_source, inner, _) => {
+            let inner_source = if fn_source.is_empty() {
+                source
+            } else {
+                Some(fn_source.as_str())
+            };
+
+            ErrorDetails {
+                r#type: atoms::in_function_call(),
+                message: format!("Error in call to function '{}'", name),
+                inner: Some(encode_evaluation_error(env, inner, inner_source)),
+                ..details
+            }
+            .encode(env)
+        }
+        EvalAltResult::ErrorInModule(path, inner, _) => {
+            let inner_source = if path.is_empty() {
+                source
+            } else {
+                Some(path.as_str())
+            };
+
+            ErrorDetails {
+                r#type: atoms::in_module(),
+                message: format!("Error in module '{}'", path),
+                inner: Some(encode_evaluation_error(env, inner, inner_source)),
+                ..details
+            }
+            .encode(env)
+        }
+        EvalAltResult::ErrorTerminated(value, _) => {
+            if let Some(cancel_token) = value.read_lock::<ResourceArc<CancelTokenResource>>() {
+                let details = ErrorDetails {
+                    r#type: atoms::terminated(),
+                    message: message_without_position(err),
+                    ..details
+                }
+                .encode(env);
+
+                return details
+                    .map_put(atoms::reason(), cancel_token.reason(env))
+                    .unwrap_or(details);
+            }
+
+            let (error_atom, message) = match value.read_lock::<Timeout>() {
+                Some(timeout) => (atoms::timeout(), timeout_message(&timeout)),
+                None => (atoms::terminated(), message_without_position(err)),
+            };
+
+            ErrorDetails {
+                r#type: error_atom,
+                message,
+                ..details
+            }
+            .encode(env)
+        }
+        EvalAltResult::ErrorParsing(err_type, position) => {
+            encode_parse_error(env, err_type, *position, source)
+        }
+        EvalAltResult::ErrorRuntime(value, _) => {
+            let details = ErrorDetails {
+                r#type: atoms::runtime(),
+                message: message_without_position(err),
+                ..details
+            }
+            .encode(env);
+
+            details
+                .map_put(atoms::value(), thrown_value(env, value))
+                .unwrap_or(details)
+        }
+        _ => ErrorDetails {
+            r#type: error_atom(env, err),
+            message: message_without_position(err),
+            ..details
+        }
+        .encode(env),
+    }
+}
+
+/// The message of an error, without the position appended by its `Display` implementation.
+fn message_without_position(err: &EvalAltResult) -> String {
+    let message = err.to_string();
+    let position = err.position();
+
+    if position.is_none() {
+        return message;
+    }
+
+    message
+        .strip_suffix(&format!(" ({})", position))
+        .map(str::to_string)
+        .unwrap_or(message)
+}
+
+/// The value thrown by a script, or `nil` if it cannot be converted.
+fn thrown_value<'a>(env: Env<'a>, value: &Dynamic) -> Term<'a> {
+    from_dynamic(env, value.clone()).unwrap_or_else(|_| atoms::nil().encode(env))
+}
+
+fn timeout_message(timeout: &Timeout) -> String {
+    format!("Script timed out after {} ms", timeout.0)
+}
+
+/// The type of an evaluation error, as the name of its atom.
+///
+/// Loop breaks and returns never escape an evaluation, as Rhai handles them at loop and function
+/// boundaries, so they are only mapped for completeness.
+fn error_name(err: &EvalAltResult) -> &'static str {
+    match err {
+        EvalAltResult::ErrorSystem(_, err) if err.is::<ModuleForbiddenError>() => {
+            "module_forbidden"
+        }
+        EvalAltResult::ErrorSystem(_, _) => "system",
+        EvalAltResult::ErrorParsing(_, _) => "parsing",
+        EvalAltResult::ErrorVariableExists(_, _) => "variable_exists",
+        EvalAltResult::ErrorForbiddenVariable(_, _) => "forbidden_variable",
+        EvalAltResult::ErrorVariableNotFound(_, _) => "variable_not_found",
+        EvalAltResult::ErrorPropertyNotFound(_, _) => "property_not_found",
+        EvalAltResult::ErrorIndexNotFound(_, _) => "index_not_found",
+        EvalAltResult::ErrorFunctionNotFound(_, _) => "function_not_found",
+        EvalAltResult::ErrorModuleNotFound(_, _) => "module_not_found",
+        EvalAltResult::ErrorInFunctionCall(_, _, _, _) => "in_function_call",
+        EvalAltResult::ErrorInModule(_, _, _) => "in_module",
+        EvalAltResult::ErrorUnboundThis(_) => "unbound_this",
+        EvalAltResult::ErrorMismatchDataType(_, _, _) => "mismatch_data_type",
+        EvalAltResult::ErrorMismatchOutputType(_, _, _) => "mismatch_output_type",
+        EvalAltResult::ErrorIndexingType(_, _) => "indexing_type",
+        EvalAltResult::ErrorArrayBounds(_, _, _) => "array_bounds",
+        EvalAltResult::ErrorStringBounds(_, _, _) => "string_bounds",
+        EvalAltResult::ErrorBitFieldBounds(_, _, _) => "bit_field_bounds",
+        EvalAltResult::ErrorFor(_) => "for",
+        EvalAltResult::ErrorDataRace(_, _) => "data_race",
+        EvalAltResult::ErrorAssignmentToConstant(_, _) => "assignment_to_constant",
+        EvalAltResult::ErrorDotExpr(_, _) => "dot_expr",
+        EvalAltResult::ErrorArithmetic(_, _) => "arithmetic",
+        EvalAltResult::ErrorTooManyOperations(_) => "too_many_operations",
+        EvalAltResult::ErrorTooManyModules(_) => "too_many_modules",
+        EvalAltResult::ErrorStackOverflow(_) => "stack_overflow",
+        EvalAltResult::ErrorDataTooLarge(_, _) => "data_too_large",
+        EvalAltResult::ErrorTerminated(_, _) => "terminated",
+        EvalAltResult::ErrorCustomSyntax(_, _, _) => "custom_syntax",
+        EvalAltResult::ErrorRuntime(_, _) => "runtime",
+        EvalAltResult::ErrorNonPureMethodCallOnConstant(_, _) => "non_pure_method_call_on_constant",
+        EvalAltResult::LoopBreak(_, _, _) => "loop_break",
+        EvalAltResult::Return(_, _) => "return",
+        _ => "unknown",
+    }
+}
+
+fn error_atom(env: Env, err: &EvalAltResult) -> Atom {
+    Atom::from_str(env, error_name(err)).unwrap_or_else(|_| atoms::unknown())
+}
+
+/// The kind of a parse error. Errors in the script text are reported by the kind of the lexer error.
+#[allow(deprecated)]
+fn parse_error_atom(err_type: &ParseErrorType) -> Atom {
+    match err_type {
+        ParseErrorType::UnexpectedEOF => atoms::unexpected_eof(),
+        ParseErrorType::BadInput(err) => match err {
+            LexError::UnexpectedInput(_) => atoms::unexpected_input(),
+            LexError::UnterminatedString => atoms::unterminated_string(),
+            LexError::StringTooLong(_) => atoms::string_too_long(),
+            LexError::MalformedEscapeSequence(_) => atoms::malformed_escape_sequence(),
+            LexError::MalformedNumber(_) => atoms::malformed_number(),
+            LexError::MalformedChar(_) => atoms::malformed_char(),
+            LexError::MalformedIdentifier(_) => atoms::malformed_identifier(),
+            LexError::ImproperSymbol(_, _) => atoms::improper_symbol(),
+            _ => atoms::bad_input(),
+        },
+        ParseErrorType::UnknownOperator(_) => atoms::unknown_operator(),
+        ParseErrorType::MissingToken(_, _) => atoms::missing_token(),
+        ParseErrorType::MissingSymbol(_) => atoms::missing_symbol(),
+        ParseErrorType::MalformedCallExpr(_) => atoms::malformed_call_expr(),
+        ParseErrorType::MalformedIndexExpr(_) => atoms::malformed_index_expr(),
+        ParseErrorType::MalformedInExpr(_) => atoms::malformed_in_expr(),
+        ParseErrorType::MalformedCapture(_) => atoms::malformed_capture(),
+        ParseErrorType::DuplicatedProperty(_) => atoms::duplicated_property(),
+        ParseErrorType::DuplicatedSwitchCase => atoms::duplicated_switch_case(),
+        ParseErrorType::DuplicatedVariable(_) => atoms::duplicated_variable(),
+        ParseErrorType::WrongSwitchIntegerCase => atoms::wrong_switch_integer_case(),
+        ParseErrorType::WrongSwitchDefaultCase => atoms::wrong_switch_default_case(),
+        ParseErrorType::WrongSwitchCaseCondition => atoms::wrong_switch_case_condition(),
+        ParseErrorType::PropertyExpected => atoms::property_expected(),
+        ParseErrorType::VariableExpected => atoms::variable_expected(),
+        ParseErrorType::ForbiddenVariable(_) => atoms::forbidden_variable(),
+        ParseErrorType::Reserved(_) => atoms::reserved(),
+        ParseErrorType::MismatchedType(_, _) => atoms::mismatched_type(),
+        ParseErrorType::ExprExpected(_) => atoms::expr_expected(),
+        ParseErrorType::WrongDocComment => atoms::wrong_doc_comment(),
+        ParseErrorType::WrongFnDefinition => atoms::wrong_fn_definition(),
+        ParseErrorType::FnDuplicatedDefinition(_, _) => atoms::fn_duplicated_definition(),
+        ParseErrorType::FnMissingName => atoms::fn_missing_name(),
+        ParseErrorType::FnMissingParams(_) => atoms::fn_missing_params(),
+        ParseErrorType::FnDuplicatedParam(_, _) => atoms::fn_duplicated_param(),
+        ParseErrorType::FnMissingBody(_) => atoms::fn_missing_body(),
+        ParseErrorType::WrongExport => atoms::wrong_export(),
+        ParseErrorType::AssignmentToConstant(_) => atoms::assignment_to_constant(),
+        ParseErrorType::AssignmentToInvalidLHS(_) => atoms::assignment_to_invalid_lhs(),
+        ParseErrorType::VariableExists(_) => atoms::variable_exists(),
+        ParseErrorType::VariableUndefined(_) => atoms::variable_undefined(),
+        ParseErrorType::ModuleUndefined(_) => atoms::module_undefined(),
+        ParseErrorType::ExprTooDeep => atoms::expr_too_deep(),
+        ParseErrorType::TooManyFunctions => atoms::too_many_functions(),
+        ParseErrorType::LiteralTooLarge(_, _) => atoms::literal_too_large(),
+        ParseErrorType::LoopBreak => atoms::loop_break(),
+        _ => atoms::unknown(),
+    }
+}
+
+fn make_reason_tuple(env: Env, atom: Atom, err_str: String) -> Term {
     (atom, err_str.encode(env)).encode(env)
 }
+
+#[cfg(test)]
+mod tests {
+    use rhai::{Dynamic, EvalAltResult, Position};
+
+    use super::error_name;
+
+    // Rhai handles loop breaks and returns before they escape an evaluation, so scripts cannot
+    // reach these mappings.
+    #[test]
+    fn control_flow_error_names() {
+        let loop_break = EvalAltResult::LoopBreak(true, Dynamic::UNIT, Position::NONE);
+        let return_value = EvalAltResult::Return(Dynamic::UNIT, Position::NONE);
+
+        assert_eq!(error_name(&loop_break), "loop_break");
+        assert_eq!(error_name(&return_value), "return");
+    }
+}
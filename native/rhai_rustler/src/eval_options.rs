@@ -0,0 +1,174 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use rhai::Dynamic;
+use rustler::{NifMap, ResourceArc};
+
+use crate::{
+    cancel_token::CancelTokenResource,
+    error::{catch_panic, RhaiRustlerError},
+    lock,
+    output::{Output, OutputBufferResource},
+    types::{MapKeys, Tuples},
+};
+
+/// Per-evaluation options passed to the evaluation NIFs.
+#[derive(NifMap)]
+pub struct EvalOptions {
+    pub cancel_token: Option<ResourceArc<CancelTokenResource>>,
+    /// Wall-clock time limit in milliseconds.
+    pub timeout: Option<u64>,
+    /// Buffer capturing the `print` and `debug` output instead of the engine output target.
+    pub output: Option<ResourceArc<OutputBufferResource>>,
+    /// Encode errors as maps with their position, source and call stack.
+    pub detailed_errors: bool,
+    /// Keys of the object maps returned to Elixir.
+    pub map_keys: MapKeys,
+    /// How tuples passed to the evaluation are converted.
+    pub tuples: Tuples,
+}
+
+/// Termination value of a script that ran past its deadline, holding the timeout in milliseconds.
+#[derive(Clone)]
+pub struct Timeout(pub u64);
+
+struct EvalContext {
+    cancel_token: Option<ResourceArc<CancelTokenResource>>,
+    timeout: Option<(u64, Instant)>,
+    output: Option<ResourceArc<OutputBufferResource>>,
+    map_keys: MapKeys,
+    tuples: Tuples,
+}
+
+thread_local! {
+    /// Context of the evaluation running on the current thread, checked by `on_progress`.
+    static CURRENT: RefCell<Option<EvalContext>> = const { RefCell::new(None) };
+}
+
+impl EvalOptions {
+    /// Run an evaluation with these options made available to the engine callbacks.
+    ///
+    /// The deadline starts counting when the evaluation starts. A panic during the evaluation is
+    /// returned as a `:panic` error instead of unwinding into the NIF.
+    pub fn run<T, E>(&self, eval: impl FnOnce() -> Result<T, E>) -> Result<T, RhaiRustlerError>
+    where
+        E: Into<RhaiRustlerError>,
+    {
+        self.run_with_source(None, eval)
+    }
+
+    /// Run the evaluation of a script with a source, reported by detailed errors.
+    pub fn run_with_source<T, E>(
+        &self,
+        source: Option<&str>,
+        eval: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, RhaiRustlerError>
+    where
+        E: Into<RhaiRustlerError>,
+    {
+        let context = EvalContext {
+            cancel_token: self.cancel_token.clone(),
+            timeout: self
+                .timeout
+                .map(|timeout| (timeout, Instant::now() + Duration::from_millis(timeout))),
+            output: self.output.clone(),
+            map_keys: self.map_keys,
+            tuples: self.tuples,
+        };
+
+        let previous = CURRENT.with(|current| current.replace(Some(context)));
+        let result = catch_panic(|| eval().map_err(Into::into));
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+
+        result.map_err(|err| {
+            if self.detailed_errors {
+                err.detailed(source)
+            } else {
+                err
+            }
+        })
+    }
+}
+
+/// Progress callback installed on every engine.
+pub fn on_progress(_operations: u64) -> Option<Dynamic> {
+    termination()
+}
+
+/// The value the running script must be terminated with, if any.
+///
+/// A cancelled script is terminated with its cancellation token, so the error encoder can read the
+/// reason from it, and a script past its deadline with a `Timeout`.
+pub fn termination() -> Option<Dynamic> {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let context = current.as_ref()?;
+
+        if let Some(cancel_token) = &context.cancel_token {
+            if cancel_token.is_cancelled() {
+                return Some(Dynamic::from(cancel_token.clone()));
+            }
+        }
+
+        match context.timeout {
+            Some((timeout, deadline)) if Instant::now() >= deadline => {
+                Some(Dynamic::from(Timeout(timeout)))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Time left before the deadline of the evaluation running on the current thread, if it has one.
+pub fn time_left() -> Option<Duration> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .and_then(|context| context.timeout)
+            .map(|(_, deadline)| deadline.saturating_duration_since(Instant::now()))
+    })
+}
+
+/// Keys of the object maps of the evaluation running on the current thread, passed to Elixir
+/// functions it calls.
+pub fn map_keys() -> MapKeys {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map_or(MapKeys::default(), |context| context.map_keys)
+    })
+}
+
+/// How tuples are converted for the evaluation running on the current thread, including the
+/// values returned by the Elixir functions it calls.
+pub fn tuples() -> Tuples {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map_or(Tuples::default(), |context| context.tuples)
+    })
+}
+
+/// Capture output into the buffer of the evaluation running on the current thread.
+///
+/// The output is given back if the evaluation does not capture its output.
+pub fn capture(output: Output) -> Result<(), Output> {
+    CURRENT.with(|current| {
+        match current
+            .borrow()
+            .as_ref()
+            .and_then(|context| context.output.as_ref())
+        {
+            Some(buffer) => {
+                lock::lock(&buffer.output).push(output);
+                Ok(())
+            }
+            None => Err(output),
+        }
+    })
+}
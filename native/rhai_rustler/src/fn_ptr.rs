@@ -0,0 +1,76 @@
+use rhai::{Dynamic, FnPtr, AST};
+use rustler::{Env, NifStruct, Reference, Resource, ResourceArc, Term};
+
+use crate::{
+    engine::EngineResource,
+    error::{catch_panic, RhaiRustlerError},
+    eval_options::EvalOptions,
+    types::{from_dynamic_with, to_dynamic_with},
+};
+
+pub struct FnPtrResource {
+    pub fn_ptr: FnPtr,
+    /// Script-defined functions of the AST the function pointer was created in, needed to call
+    /// pointers to named functions such as `Fn("name")`.
+    pub ast: AST,
+}
+
+#[rustler::resource_impl]
+impl Resource for FnPtrResource {}
+
+/// Handle to a Rhai function pointer or closure, encoded as a `%Rhai.FnPtr{}` struct.
+#[derive(NifStruct)]
+#[module = "Rhai.FnPtr"]
+pub struct FnPtrHandle<'a> {
+    pub name: String,
+    pub resource: ResourceArc<FnPtrResource>,
+    pub reference: Reference<'a>,
+}
+
+impl<'a> FnPtrHandle<'a> {
+    pub fn new(env: Env<'a>, fn_ptr: FnPtr, ast: Option<&AST>) -> Self {
+        FnPtrHandle {
+            name: fn_ptr.fn_name().to_string(),
+            resource: ResourceArc::new(FnPtrResource {
+                fn_ptr,
+                ast: ast.map_or_else(AST::empty, AST::clone_functions_only),
+            }),
+            reference: env.make_ref(),
+        }
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn fn_ptr_call<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<FnPtrResource>,
+    engine_resource: ResourceArc<EngineResource>,
+    args: Vec<Term<'a>>,
+    options: EvalOptions,
+) -> Result<Term<'a>, RhaiRustlerError> {
+    catch_panic(|| {
+        let engine = engine_resource.read();
+
+        let args: Vec<Dynamic> = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                to_dynamic_with(env, arg, options.tuples)
+                    .map_err(|err| err.within(format!("args[{}]", i)))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let result = options.run_with_source(resource.ast.source(), || {
+            resource
+                .fn_ptr
+                .call::<Dynamic>(&engine, &resource.ast, args)
+        })?;
+
+        Ok(from_dynamic_with(
+            env,
+            result,
+            Some(&resource.ast),
+            options.map_keys,
+        )?)
+    })
+}
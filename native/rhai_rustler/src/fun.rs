@@ -0,0 +1,97 @@
+use std::{any::TypeId, iter};
+
+use rhai::{Dynamic, Engine, FnPtr};
+use rustler::{Encoder, Env, LocalPid, Term};
+
+use crate::{callback, error::ConversionError, eval_options::time_left, types::from_dynamic};
+
+mod atoms {
+    rustler::atoms! {
+        rhai_call,
+        dispatcher = "Elixir.Rhai.Dispatcher"
+    }
+}
+
+/// Name of the function calling Elixir funs. Funs are passed to scripts as pointers to this
+/// function, with the fun curried as the first argument.
+const CALL_FUN: &str = "elixir_fun";
+
+/// Maximum number of arguments of the Elixir funs called by scripts.
+const MAX_ARITY: usize = 16;
+
+/// An Elixir fun passed to a script, run by the `Rhai.Dispatcher` process when called.
+#[derive(Clone)]
+struct ElixirFun {
+    name: String,
+    /// The fun in the external term format.
+    fun: Vec<u8>,
+    dispatcher: LocalPid,
+}
+
+/// Convert an Elixir fun into a function pointer scripts can call with `f.call(...)` or `call(f, ...)`.
+pub fn to_fn_ptr(env: Env, fun: Term) -> Result<FnPtr, ConversionError> {
+    let dispatcher = env.whereis_pid(atoms::dispatcher()).ok_or_else(|| {
+        ConversionError::new(format!(
+            "Cannot convert {:?}, the rhai_rustler application is not started",
+            fun
+        ))
+    })?;
+
+    let mut fn_ptr = FnPtr::new(CALL_FUN).expect("valid function name");
+    fn_ptr.add_curry(Dynamic::from(ElixirFun {
+        name: format!("{:?}", fun),
+        fun: fun.to_binary().as_slice().to_vec(),
+        dispatcher,
+    }));
+
+    Ok(fn_ptr)
+}
+
+/// The Elixir fun a function pointer was converted from, if any.
+pub fn from_fn_ptr<'a>(env: Env<'a>, fn_ptr: &FnPtr) -> Option<Term<'a>> {
+    match fn_ptr.curry() {
+        [fun] if fn_ptr.fn_name() == CALL_FUN => fun
+            .read_lock::<ElixirFun>()
+            .and_then(|fun| env.binary_to_term(&fun.fun))
+            .map(|(fun, _)| fun),
+        _ => None,
+    }
+}
+
+/// Register the function calling Elixir funs, for every supported number of arguments.
+pub fn register(engine: &mut Engine) {
+    for arity in 0..=MAX_ARITY {
+        let arg_types = iter::once(TypeId::of::<ElixirFun>())
+            .chain(iter::repeat_n(TypeId::of::<Dynamic>(), arity))
+            .collect::<Vec<_>>();
+
+        engine.register_raw_fn(CALL_FUN, arg_types, |context, args| {
+            let fun = std::mem::take(args[0]).cast::<ElixirFun>();
+            let name = fun.name.clone();
+            let args: Vec<Dynamic> = args[1..]
+                .iter_mut()
+                .map(|arg| std::mem::take(*arg))
+                .collect();
+            // Funs may run for as long as the evaluation, with no limit if it has no timeout.
+            let call_timeout = time_left();
+
+            callback::call(fun.dispatcher, call_timeout, move |env, callback| {
+                let (fun_term, _) = env
+                    .binary_to_term(&fun.fun)
+                    .ok_or_else(|| ConversionError::new(format!("Cannot decode {}", fun.name)))?;
+                let args: Vec<Term> = args
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, arg)| {
+                        from_dynamic(env, arg).map_err(|err| err.within(format!("args[{}]", i)))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Ok((atoms::rhai_call(), callback, fun_term, args).encode(env))
+            })
+            .map_err(|err| {
+                err.into_eval_error(&name, call_timeout.unwrap_or_default(), context.position())
+            })
+        });
+    }
+}
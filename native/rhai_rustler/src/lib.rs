@@ -1,6 +1,20 @@
 mod ast;
+mod callback;
+mod cancel_token;
+mod collections;
+mod date_time;
+#[cfg(feature = "decimal")]
+mod decimal;
 mod engine;
 mod error;
+mod eval_options;
+mod fn_ptr;
+mod fun;
+mod lock;
+mod module_resolver;
+mod opaque;
+mod output;
+mod pool;
 mod scope;
 mod types;
 
@@ -0,0 +1,41 @@
+use std::sync::{
+    Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+};
+
+use crate::error::BusyError;
+
+/// Exclusive access to a mutex, even if a thread panicked while holding it.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Shared access to a resource, waiting for a writer to release it.
+///
+/// Writers never wait for the lock, so they only hold it for as long as they need to update the resource.
+pub fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Shared access to a resource, failing if it is being updated.
+pub fn try_read<'a, T>(
+    lock: &'a RwLock<T>,
+    name: &'static str,
+) -> Result<RwLockReadGuard<'a, T>, BusyError> {
+    match lock.try_read() {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::Poisoned(err)) => Ok(err.into_inner()),
+        Err(TryLockError::WouldBlock) => Err(BusyError(name)),
+    }
+}
+
+/// Exclusive access to a resource, failing if it is being used.
+pub fn try_write<'a, T>(
+    lock: &'a RwLock<T>,
+    name: &'static str,
+) -> Result<RwLockWriteGuard<'a, T>, BusyError> {
+    match lock.try_write() {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::Poisoned(err)) => Ok(err.into_inner()),
+        Err(TryLockError::WouldBlock) => Err(BusyError(name)),
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{PoisonError, RwLock},
+    time::Duration,
+};
+
+use rhai::{
+    module_resolvers::{FileModuleResolver, ModuleResolversCollection, StaticModuleResolver},
+    Engine, EvalAltResult, Module, ModuleResolver, Position, Scope, Shared, AST,
+};
+use rustler::{Encoder, LocalPid, NifMap, NifUnitEnum, Resource, ResourceArc};
+
+use crate::{
+    ast::ASTResource,
+    callback,
+    engine::EngineResource,
+    error::{catch_panic, ModuleForbiddenError, RhaiRustlerError},
+    eval_options::{termination, time_left, EvalOptions},
+    lock,
+};
+
+mod atoms {
+    rustler::atoms! {
+        rhai_import
+    }
+}
+
+/// Error returned for imports of module paths that are not allowed.
+#[derive(NifUnitEnum, Clone, Copy)]
+pub enum ForbiddenError {
+    ModuleForbidden,
+    ModuleNotFound,
+}
+
+/// Module paths scripts are allowed to import, as glob patterns.
+#[derive(NifMap)]
+pub struct ModuleAccess {
+    /// Paths that can be imported, or `None` to allow any path not denied.
+    pub allow: Option<Vec<String>>,
+    pub deny: Vec<String>,
+    pub forbidden_error: ForbiddenError,
+}
+
+impl ModuleAccess {
+    pub fn is_restricted(&self) -> bool {
+        self.allow.is_some() || !self.deny.is_empty()
+    }
+
+    /// Paths that are not normal, for example `tenant/../secret`, are never allowed, since
+    /// resolvers could import them from outside of the paths matched by the patterns.
+    fn is_allowed(&self, path: &str) -> bool {
+        let matches = |pattern: &String| glob_match(pattern, path);
+
+        is_normal(path)
+            && !self.deny.iter().any(matches)
+            && self
+                .allow
+                .as_ref()
+                .is_none_or(|allow| allow.iter().any(matches))
+    }
+}
+
+/// Module resolvers of an engine, only resolving the module paths allowed by its `ModuleAccess`.
+pub struct RestrictedModuleResolver {
+    pub resolvers: ModuleResolversCollection,
+    pub access: ModuleAccess,
+}
+
+impl ModuleResolver for RestrictedModuleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        if self.access.is_allowed(path) {
+            return self.resolvers.resolve(engine, source, path, pos);
+        }
+
+        Err(match self.access.forbidden_error {
+            // The system error is wrapped so that the error carries the path and the position of
+            // the import, and is still reported as `:module_forbidden`.
+            ForbiddenError::ModuleForbidden => EvalAltResult::ErrorInModule(
+                path.to_string(),
+                EvalAltResult::ErrorSystem(
+                    String::new(),
+                    Box::new(ModuleForbiddenError(path.to_string())),
+                )
+                .into(),
+                pos,
+            ),
+            ForbiddenError::ModuleNotFound => {
+                EvalAltResult::ErrorModuleNotFound(path.to_string(), pos)
+            }
+        }
+        .into())
+    }
+}
+
+/// Whether a module path is relative, uses `/` as separator and has no empty, `.` or `..`
+/// segments.
+fn is_normal(path: &str) -> bool {
+    !path.contains('\\')
+        && path
+            .split('/')
+            .all(|segment| !matches!(segment, "" | "." | ".."))
+}
+
+/// Match a path against a glob pattern, where `*` matches any characters but `/`, `**` matches
+/// any characters and `?` matches any character but `/`.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[char], path: &[char]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            ['*', '*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            ['*', rest @ ..] => {
+                let segment_end = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+
+                (0..=segment_end).any(|i| matches(rest, &path[i..]))
+            }
+            ['?', rest @ ..] => matches!(path, [c, tail @ ..] if *c != '/' && matches(rest, tail)),
+            [c, rest @ ..] => matches!(path, [p, tail @ ..] if p == c && matches(rest, tail)),
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+
+    matches(&pattern, &path)
+}
+
+/// Options of the file module resolver.
+#[derive(NifMap)]
+pub struct FileModuleResolverOptions {
+    pub base_path: Option<String>,
+    pub extension: Option<String>,
+    pub cache: bool,
+    /// Reject imports of files outside of the base path.
+    pub sandbox: bool,
+}
+
+/// File module resolver, optionally confined to its base path.
+pub struct SandboxedFileModuleResolver {
+    resolver: FileModuleResolver,
+    /// Canonical base path imported files must be in, if sandboxed.
+    sandbox: Option<PathBuf>,
+}
+
+impl SandboxedFileModuleResolver {
+    pub fn new(options: FileModuleResolverOptions) -> Self {
+        let mut resolver = FileModuleResolver::new();
+
+        if let Some(base_path) = options.base_path {
+            resolver.set_base_path(base_path);
+        }
+        if let Some(extension) = options.extension {
+            resolver.set_extension(extension);
+        }
+        resolver.enable_cache(options.cache);
+
+        let sandbox = options.sandbox.then(|| {
+            let base_path = resolver
+                .base_path()
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+            let base_path = base_path.canonicalize().unwrap_or(base_path);
+
+            // Relative imports are resolved from the sandbox, never from the importing module.
+            resolver.set_base_path(base_path.clone());
+            base_path
+        });
+
+        SandboxedFileModuleResolver { resolver, sandbox }
+    }
+}
+
+impl ModuleResolver for SandboxedFileModuleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        if let Some(sandbox) = &self.sandbox {
+            let file_path = self
+                .resolver
+                .get_file_path(path, source.and_then(|source| Path::new(source).parent()));
+
+            // Resolving `..` and symbolic links also tells whether the file exists.
+            match file_path.canonicalize() {
+                Ok(file_path) if file_path.starts_with(sandbox) => (),
+                _ => return Err(EvalAltResult::ErrorModuleNotFound(path.to_string(), pos).into()),
+            }
+        }
+
+        self.resolver.resolve(engine, source, path, pos)
+    }
+}
+
+/// Modules compiled from sources registered by Elixir, which can be updated while engines use them.
+pub struct StaticModuleResolverResource {
+    pub resolver: RwLock<StaticModuleResolver>,
+}
+
+#[rustler::resource_impl]
+impl Resource for StaticModuleResolverResource {}
+
+/// Module resolver reading the modules of a `StaticModuleResolverResource` at import time, so
+/// engines see the modules added or removed after the resolver was set.
+pub struct SharedStaticModuleResolver(pub ResourceArc<StaticModuleResolverResource>);
+
+impl ModuleResolver for SharedStaticModuleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        lock::read(&self.0.resolver).resolve(engine, source, path, pos)
+    }
+}
+
+#[rustler::nif]
+fn static_module_resolver_new() -> ResourceArc<StaticModuleResolverResource> {
+    ResourceArc::new(StaticModuleResolverResource {
+        resolver: RwLock::new(StaticModuleResolver::new()),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn static_module_resolver_add(
+    resource: ResourceArc<StaticModuleResolverResource>,
+    engine_resource: ResourceArc<EngineResource>,
+    path: String,
+    source: String,
+    options: EvalOptions,
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        let engine = engine_resource.read();
+
+        // The module is evaluated before locking the resolver, as it may import other modules from it.
+        let module = options.run(|| -> Result<Module, RhaiRustlerError> {
+            let mut ast = engine.compile(source)?;
+            ast.set_source(path.as_str());
+
+            Ok(Module::eval_ast_as_new(Scope::new(), &ast, &engine)?)
+        })?;
+
+        resource
+            .resolver
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path, module);
+
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn static_module_resolver_remove(
+    resource: ResourceArc<StaticModuleResolverResource>,
+    path: String,
+) -> bool {
+    resource
+        .resolver
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&path)
+        .is_some()
+}
+
+#[rustler::nif]
+fn static_module_resolver_paths(
+    resource: ResourceArc<StaticModuleResolverResource>,
+) -> Vec<String> {
+    lock::read(&resource.resolver)
+        .paths()
+        .map(str::to_string)
+        .collect()
+}
+
+#[rustler::nif]
+fn static_module_resolver_contains_path(
+    resource: ResourceArc<StaticModuleResolverResource>,
+    path: String,
+) -> bool {
+    lock::read(&resource.resolver).contains_path(&path)
+}
+
+/// Resolver asking an Elixir process for the modules imported by scripts.
+pub struct ProcessModuleResolverResource {
+    pub pid: LocalPid,
+    pub timeout: Duration,
+    /// Resolved modules by path, if caching is enabled.
+    pub cache: Option<RwLock<HashMap<String, Shared<Module>>>>,
+}
+
+#[rustler::resource_impl]
+impl Resource for ProcessModuleResolverResource {}
+
+impl ProcessModuleResolverResource {
+    /// Ask the process for the source or the compiled AST of the module.
+    fn request(
+        &self,
+        engine: &Engine,
+        path: &str,
+        pos: Position,
+    ) -> Result<AST, Box<EvalAltResult>> {
+        // Do not wait for the reply past the deadline of the evaluation.
+        let call_timeout =
+            time_left().map_or(self.timeout, |time_left| time_left.min(self.timeout));
+        let request_path = path.to_string();
+
+        let reply = callback::call(self.pid, Some(call_timeout), move |env, callback| {
+            Ok((atoms::rhai_import(), callback, request_path).encode(env))
+        })
+        .map_err(|_| match termination() {
+            Some(value) => EvalAltResult::ErrorTerminated(value, pos),
+            None => EvalAltResult::ErrorModuleNotFound(path.to_string(), pos),
+        })?;
+
+        if let Some(ast) = reply.read_lock::<ResourceArc<ASTResource>>() {
+            return Ok(ast.read().clone());
+        }
+
+        match reply.into_immutable_string() {
+            Ok(source) => Ok(engine.compile(source.as_str())?),
+            Err(type_name) => Err(EvalAltResult::ErrorMismatchOutputType(
+                "string or Rhai.AST".to_string(),
+                type_name.to_string(),
+                pos,
+            )
+            .into()),
+        }
+    }
+}
+
+/// Module resolver of a `ProcessModuleResolverResource`.
+pub struct SharedProcessModuleResolver(pub ResourceArc<ProcessModuleResolverResource>);
+
+impl ModuleResolver for SharedProcessModuleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        _source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        let resource = &self.0;
+
+        if let Some(cache) = &resource.cache {
+            if let Some(module) = lock::read(cache).get(path) {
+                return Ok(module.clone());
+            }
+        }
+
+        let module: Shared<Module> = resource
+            .request(engine, path, pos)
+            .and_then(|mut ast| {
+                if ast.source().is_none() {
+                    ast.set_source(path);
+                }
+
+                Module::eval_ast_as_new(Scope::new(), &ast, engine)
+            })
+            // Resolvers of a `ModuleResolversCollection` may only fail with these two errors, the
+            // collection returning the error wrapped in `ErrorInModule`.
+            .map_err(|err| match *err {
+                EvalAltResult::ErrorModuleNotFound(..) => err,
+                _ => EvalAltResult::ErrorInModule(path.to_string(), err, pos).into(),
+            })?
+            .into();
+
+        if let Some(cache) = &resource.cache {
+            cache
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.to_string(), module.clone());
+        }
+
+        Ok(module)
+    }
+}
+
+#[rustler::nif]
+fn process_module_resolver_new(
+    pid: LocalPid,
+    timeout: u64,
+    cache: bool,
+) -> ResourceArc<ProcessModuleResolverResource> {
+    ResourceArc::new(ProcessModuleResolverResource {
+        pid,
+        timeout: Duration::from_millis(timeout),
+        cache: cache.then(|| RwLock::new(HashMap::new())),
+    })
+}
+
+#[rustler::nif]
+fn process_module_resolver_invalidate(
+    resource: ResourceArc<ProcessModuleResolverResource>,
+    path: String,
+) -> bool {
+    resource.cache.as_ref().is_some_and(|cache| {
+        cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&path)
+            .is_some()
+    })
+}
+
+#[rustler::nif]
+fn process_module_resolver_invalidate_all(resource: ResourceArc<ProcessModuleResolverResource>) {
+    if let Some(cache) = &resource.cache {
+        cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+use rhai::{def_package, FuncRegistration, Module, Variant};
+use rustler::{Env, Term};
+
+use crate::error::ConversionError;
+
+/// A term scripts can only compare, print and pass around, kept in the external term format so
+/// that it outlives the call it was received in.
+#[derive(Clone, PartialEq)]
+struct OpaqueTerm {
+    term: Vec<u8>,
+    name: String,
+}
+
+impl OpaqueTerm {
+    fn new(term: Term) -> Self {
+        OpaqueTerm {
+            term: term.to_binary().as_slice().to_vec(),
+            name: format!("{:?}", term),
+        }
+    }
+
+    fn decode<'a>(&self, env: Env<'a>) -> Result<Term<'a>, ConversionError> {
+        env.binary_to_term(&self.term)
+            .map(|(term, _)| term)
+            .ok_or_else(|| ConversionError::new(format!("Cannot decode {}", self.name)))
+    }
+}
+
+impl fmt::Display for OpaqueTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+macro_rules! opaque_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, PartialEq)]
+        pub struct $name(OpaqueTerm);
+
+        impl $name {
+            pub fn new(term: Term) -> Self {
+                $name(OpaqueTerm::new(term))
+            }
+
+            /// The original term.
+            pub fn decode<'a>(&self, env: Env<'a>) -> Result<Term<'a>, ConversionError> {
+                self.0.decode(env)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+opaque_type!(
+    /// An Elixir pid, so that scripts can return the pid of the caller untouched.
+    Pid
+);
+
+opaque_type!(
+    /// An Elixir reference, such as the correlation reference of a request.
+    Reference
+);
+
+opaque_type!(
+    /// An Elixir port.
+    Port
+);
+
+def_package! {
+    /// Package of the functions of the Elixir pids, references and ports passed to scripts.
+    pub OpaquePackage(lib) {
+        register::<Pid>(lib, "Pid");
+        register::<Reference>(lib, "Reference");
+        register::<Port>(lib, "Port");
+    }
+}
+
+/// Register an opaque type, which can only be compared for equality and printed.
+fn register<T>(lib: &mut Module, name: &str)
+where
+    T: Variant + Clone + PartialEq + fmt::Display,
+{
+    lib.set_custom_type::<T>(name);
+
+    FuncRegistration::new("==").set_into_module(lib, |a: T, b: T| a == b);
+    FuncRegistration::new("!=").set_into_module(lib, |a: T, b: T| a != b);
+
+    for name in ["to_string", "to_debug"] {
+        FuncRegistration::new(name).set_into_module(lib, |value: &mut T| value.to_string());
+    }
+}
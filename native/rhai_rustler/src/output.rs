@@ -0,0 +1,129 @@
+use std::{fmt, sync::Mutex};
+
+use rhai::{Engine, Position};
+use rustler::{Encoder, Env, LocalPid, Resource, ResourceArc, Term};
+
+use crate::{callback, eval_options, lock};
+
+mod atoms {
+    rustler::atoms! {
+        print,
+        debug,
+        rhai_output
+    }
+}
+
+/// Text written by a script with `print` or `debug`.
+pub enum Output {
+    Print(String),
+    Debug {
+        text: String,
+        source: Option<String>,
+        position: Position,
+    },
+}
+
+impl Encoder for Output {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Output::Print(text) => (atoms::print(), text).encode(env),
+            Output::Debug {
+                text,
+                source,
+                position,
+            } => {
+                let position = position
+                    .line()
+                    .map(|line| (line, position.position()).encode(env))
+                    .unwrap_or_else(|| rustler::types::atom::nil().to_term(env));
+
+                (atoms::debug(), text, source, position).encode(env)
+            }
+        }
+    }
+}
+
+/// Same format as the default Rhai `print` and `debug` implementations.
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Print(text) => write!(f, "{}", text),
+            Output::Debug {
+                text,
+                source,
+                position,
+            } => match (source, position.is_none()) {
+                (Some(source), true) => write!(f, "{} | {}", source, text),
+                (Some(source), false) => write!(f, "{} @ {:?} | {}", source, position, text),
+                (None, true) => write!(f, "{}", text),
+                (None, false) => write!(f, "{:?} | {}", position, text),
+            },
+        }
+    }
+}
+
+/// Where the output of scripts goes when it is not captured by the evaluation.
+#[derive(Clone, Copy)]
+pub enum OutputTarget {
+    Stdout,
+    Pid(LocalPid),
+    Discard,
+}
+
+/// Install `print` and `debug` handlers sending the output of scripts to `target`.
+pub fn set_output(engine: &mut Engine, target: OutputTarget) {
+    engine.on_print(move |text| emit(target, Output::Print(text.to_string())));
+    engine.on_debug(move |text, source, position| {
+        emit(
+            target,
+            Output::Debug {
+                text: text.to_string(),
+                source: source.map(|source| source.to_string()),
+                position,
+            },
+        )
+    });
+}
+
+fn emit(target: OutputTarget, output: Output) {
+    let Err(output) = eval_options::capture(output) else {
+        return;
+    };
+
+    match target {
+        OutputTarget::Stdout => println!("{}", output),
+        OutputTarget::Pid(pid) => {
+            // Output sent to a process that is not alive is dropped.
+            let _ = callback::send(pid, move |env| {
+                Ok((atoms::rhai_output(), output).encode(env))
+            });
+        }
+        OutputTarget::Discard => {}
+    }
+}
+
+pub struct OutputBufferResource {
+    pub output: Mutex<Vec<Output>>,
+}
+
+impl OutputBufferResource {
+    /// Take the output captured so far.
+    pub fn take(&self) -> Vec<Output> {
+        std::mem::take(&mut *lock::lock(&self.output))
+    }
+}
+
+#[rustler::resource_impl]
+impl Resource for OutputBufferResource {}
+
+#[rustler::nif]
+fn output_buffer_new() -> ResourceArc<OutputBufferResource> {
+    ResourceArc::new(OutputBufferResource {
+        output: Mutex::new(Vec::new()),
+    })
+}
+
+#[rustler::nif]
+fn output_buffer_take(resource: ResourceArc<OutputBufferResource>) -> Vec<Output> {
+    resource.take()
+}
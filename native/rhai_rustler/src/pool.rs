@@ -0,0 +1,49 @@
+use std::{
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex, OnceLock},
+    thread,
+};
+
+use crate::lock;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Thread pool running asynchronous evaluations, so they do not occupy BEAM schedulers.
+///
+/// The workers are started on first use, one per available CPU.
+static POOL: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+/// Run a job on the thread pool.
+pub fn spawn(job: impl FnOnce() + Send + 'static) {
+    POOL.get_or_init(start)
+        .send(Box::new(job))
+        .expect("the rhai_rustler thread pool is not running");
+}
+
+fn start() -> mpsc::Sender<Job> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let size = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+
+    for i in 0..size {
+        let receiver = receiver.clone();
+
+        thread::Builder::new()
+            .name(format!("rhai_rustler_worker_{}", i))
+            .spawn(move || loop {
+                let job = lock::lock(&receiver).recv();
+
+                match job {
+                    // A panicking job must not take its worker down with it.
+                    Ok(job) => {
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                    Err(_) => break,
+                }
+            })
+            .expect("failed to start a rhai_rustler worker thread");
+    }
+
+    sender
+}
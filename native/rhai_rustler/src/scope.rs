@@ -1,31 +1,48 @@
-use std::sync::Mutex;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use rhai::Scope;
-use rustler::{Encoder, Env, Resource, ResourceArc, Term};
+use rustler::{Encoder, Env, NifResult, Resource, ResourceArc, Term};
 
 use crate::{
-    error::{RhaiRustlerError, ScopeError},
-    types::{from_dynamic, to_dynamic},
+    error::{catch_panic, BusyError, RhaiRustlerError, ScopeError},
+    lock,
+    types::{from_dynamic, to_dynamic_with, Tuples},
 };
 
 pub struct ScopeResource {
-    pub scope: Mutex<Scope<'static>>,
+    pub scope: RwLock<Scope<'static>>,
+    /// How tuples pushed to the scope are converted.
+    pub tuples: Tuples,
 }
 
 #[rustler::resource_impl]
 impl Resource for ScopeResource {}
 
+/// Evaluations with a scope hold it for their whole duration, so access fails instead of waiting
+/// while the scope is used by an evaluation.
+impl ScopeResource {
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, Scope<'static>>, BusyError> {
+        lock::try_read(&self.scope, "scope")
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, Scope<'static>>, BusyError> {
+        lock::try_write(&self.scope, "scope")
+    }
+}
+
 #[rustler::nif]
-fn scope_new() -> ResourceArc<ScopeResource> {
+fn scope_new(tuples: Tuples) -> ResourceArc<ScopeResource> {
     ResourceArc::new(ScopeResource {
-        scope: Mutex::new(Scope::new()),
+        scope: RwLock::new(Scope::new()),
+        tuples,
     })
 }
 
 #[rustler::nif]
-fn scope_with_capacity(capacity: usize) -> ResourceArc<ScopeResource> {
+fn scope_with_capacity(capacity: usize, tuples: Tuples) -> ResourceArc<ScopeResource> {
     ResourceArc::new(ScopeResource {
-        scope: Mutex::new(Scope::with_capacity(capacity)),
+        scope: RwLock::new(Scope::with_capacity(capacity)),
+        tuples,
     })
 }
 
@@ -35,10 +52,16 @@ fn scope_push_dynamic<'a>(
     resource: ResourceArc<ScopeResource>,
     name: &str,
     value: Term<'a>,
-) {
-    let mut scope = resource.scope.try_lock().unwrap();
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        let mut scope = resource.write()?;
+        let value =
+            to_dynamic_with(env, &value, resource.tuples).map_err(|err| err.within(name))?;
+
+        scope.push_dynamic(name, value);
 
-    scope.push_dynamic(name, to_dynamic(env, &value));
+        Ok(())
+    })
 }
 
 #[rustler::nif]
@@ -47,24 +70,30 @@ fn scope_push_constant_dynamic<'a>(
     resource: ResourceArc<ScopeResource>,
     name: &str,
     value: Term<'a>,
-) {
-    let mut scope = resource.scope.try_lock().unwrap();
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        let mut scope = resource.write()?;
+        let value =
+            to_dynamic_with(env, &value, resource.tuples).map_err(|err| err.within(name))?;
 
-    scope.push_constant_dynamic(name, to_dynamic(env, &value));
+        scope.push_constant_dynamic(name, value);
+
+        Ok(())
+    })
 }
 
 #[rustler::nif]
-fn scope_contains(resource: ResourceArc<ScopeResource>, name: &str) -> bool {
-    let scope = resource.scope.try_lock().unwrap();
+fn scope_contains(resource: ResourceArc<ScopeResource>, name: &str) -> NifResult<bool> {
+    let scope = resource.read()?;
 
-    scope.contains(name)
+    Ok(scope.contains(name))
 }
 
 #[rustler::nif]
-fn scope_is_constant(resource: ResourceArc<ScopeResource>, name: &str) -> Option<bool> {
-    let scope = resource.scope.try_lock().unwrap();
+fn scope_is_constant(resource: ResourceArc<ScopeResource>, name: &str) -> NifResult<Option<bool>> {
+    let scope = resource.read()?;
 
-    scope.is_constant(name)
+    Ok(scope.is_constant(name))
 }
 
 #[rustler::nif]
@@ -72,40 +101,52 @@ fn scope_get_value<'a>(
     env: Env<'a>,
     resource: ResourceArc<ScopeResource>,
     name: &str,
-) -> Option<Term<'a>> {
-    let scope = resource.scope.try_lock().unwrap();
-
-    scope.get_value(name).map(|v| from_dynamic(env, v))
+) -> Result<Option<Term<'a>>, RhaiRustlerError> {
+    catch_panic(|| {
+        let scope = resource.read()?;
+
+        match scope.get_value(name) {
+            Some(value) => Ok(Some(
+                from_dynamic(env, value).map_err(|err| err.within(name))?,
+            )),
+            None => Ok(None),
+        }
+    })
 }
 
 #[rustler::nif]
-fn scope_clear(resource: ResourceArc<ScopeResource>) {
-    let mut scope = resource.scope.try_lock().unwrap();
+fn scope_clear(resource: ResourceArc<ScopeResource>) -> NifResult<()> {
+    let mut scope = resource.write()?;
 
     scope.clear();
+
+    Ok(())
 }
 
 #[rustler::nif]
-fn scope_clone_visible(resource: ResourceArc<ScopeResource>) -> ResourceArc<ScopeResource> {
-    let scope = resource.scope.try_lock().unwrap();
+fn scope_clone_visible(
+    resource: ResourceArc<ScopeResource>,
+) -> NifResult<ResourceArc<ScopeResource>> {
+    let scope = resource.read()?;
 
-    ResourceArc::new(ScopeResource {
-        scope: Mutex::new(scope.clone_visible()),
-    })
+    Ok(ResourceArc::new(ScopeResource {
+        scope: RwLock::new(scope.clone_visible()),
+        tuples: resource.tuples,
+    }))
 }
 
 #[rustler::nif]
-fn scope_is_empty(resource: ResourceArc<ScopeResource>) -> bool {
-    let scope = resource.scope.try_lock().unwrap();
+fn scope_is_empty(resource: ResourceArc<ScopeResource>) -> NifResult<bool> {
+    let scope = resource.read()?;
 
-    scope.is_empty()
+    Ok(scope.is_empty())
 }
 
 #[rustler::nif]
-fn scope_len(resource: ResourceArc<ScopeResource>) -> usize {
-    let scope = resource.scope.try_lock().unwrap();
+fn scope_len(resource: ResourceArc<ScopeResource>) -> NifResult<usize> {
+    let scope = resource.read()?;
 
-    scope.len()
+    Ok(scope.len())
 }
 
 #[rustler::nif]
@@ -113,40 +154,60 @@ fn scope_remove<'a>(
     env: Env<'a>,
     resource: ResourceArc<ScopeResource>,
     name: &str,
-) -> Option<Term<'a>> {
-    let mut scope = resource.scope.try_lock().unwrap();
-
-    scope.remove(name).map(|v| from_dynamic(env, v))
+) -> Result<Option<Term<'a>>, RhaiRustlerError> {
+    catch_panic(|| {
+        let mut scope = resource.write()?;
+
+        match scope.remove(name) {
+            Some(value) => Ok(Some(
+                from_dynamic(env, value).map_err(|err| err.within(name))?,
+            )),
+            None => Ok(None),
+        }
+    })
 }
 
 #[rustler::nif]
-fn scope_rewind(resource: ResourceArc<ScopeResource>, size: usize) {
-    let mut scope = resource.scope.try_lock().unwrap();
+fn scope_rewind(resource: ResourceArc<ScopeResource>, size: usize) -> NifResult<()> {
+    let mut scope = resource.write()?;
 
     scope.rewind(size);
+
+    Ok(())
 }
 
 #[rustler::nif]
-fn scope_iter_collect<'a>(env: Env<'a>, resource: ResourceArc<ScopeResource>) -> Vec<Term<'a>> {
-    let scope = resource.scope.try_lock().unwrap();
-    let value: Vec<Term<'a>> = scope
-        .iter()
-        .map(|(n, _, v)| (n, from_dynamic(env, v)).encode(env))
-        .collect();
-
-    value
+fn scope_iter_collect<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<ScopeResource>,
+) -> Result<Vec<Term<'a>>, RhaiRustlerError> {
+    catch_panic(|| {
+        let scope = resource.read()?;
+        let value: Vec<Term<'a>> = scope
+            .iter()
+            .map(|(n, _, v)| {
+                from_dynamic(env, v)
+                    .map(|v| (n, v).encode(env))
+                    .map_err(|err| err.within(n))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(value)
+    })
 }
 
 #[rustler::nif]
 fn scope_pop(resource: ResourceArc<ScopeResource>) -> Result<(), RhaiRustlerError> {
-    let mut scope = resource.scope.try_lock().unwrap();
+    catch_panic(|| {
+        let mut scope = resource.write()?;
 
-    if scope.is_empty() {
-        return Err(ScopeError::ErrorScopeIsEmpty.into());
-    }
-    scope.pop();
+        if scope.is_empty() {
+            return Err(ScopeError::ErrorScopeIsEmpty.into());
+        }
+        scope.pop();
 
-    Ok(())
+        Ok(())
+    })
 }
 
 #[rustler::nif]
@@ -156,14 +217,19 @@ fn scope_set_value<'a>(
     name: &str,
     value: Term<'a>,
 ) -> Result<(), RhaiRustlerError> {
-    let mut scope = resource.scope.try_lock().unwrap();
+    catch_panic(|| {
+        let mut scope = resource.write()?;
 
-    if scope.is_constant(name).unwrap_or(false) {
-        return Err(ScopeError::ErrorCannotUpdateValueOfConstant.into());
-    }
-    scope.set_value(name, to_dynamic(env, &value));
+        if scope.is_constant(name).unwrap_or(false) {
+            return Err(ScopeError::ErrorCannotUpdateValueOfConstant.into());
+        }
+        let value =
+            to_dynamic_with(env, &value, resource.tuples).map_err(|err| err.within(name))?;
 
-    Ok(())
+        scope.set_value(name, value);
+
+        Ok(())
+    })
 }
 
 #[rustler::nif]
@@ -172,8 +238,14 @@ fn scope_set_or_push<'a>(
     resource: ResourceArc<ScopeResource>,
     name: &str,
     value: Term<'a>,
-) {
-    let mut scope = resource.scope.try_lock().unwrap();
+) -> Result<(), RhaiRustlerError> {
+    catch_panic(|| {
+        let mut scope = resource.write()?;
+        let value =
+            to_dynamic_with(env, &value, resource.tuples).map_err(|err| err.within(name))?;
 
-    scope.set_or_push(name, to_dynamic(env, &value));
+        scope.set_or_push(name, value);
+
+        Ok(())
+    })
 }
@@ -1,39 +1,195 @@
-use std::collections::HashMap;
+use std::{collections::HashSet, time::Instant};
 
-use rhai::Dynamic;
-use rustler::{types::tuple::get_tuple, Encoder, Env, Term, TermType};
+use rhai::{Blob, Dynamic, FnPtr, AST};
+use rustler::{
+    types::{
+        binary::NewBinary,
+        map::MapIterator,
+        tuple::{get_tuple, make_tuple},
+    },
+    Atom, Binary, Encoder, Env, NifUnitEnum, Term, TermType,
+};
 
-pub fn from_dynamic(env: Env, value: Dynamic) -> Term {
-    match value.type_name() {
+#[cfg(feature = "decimal")]
+use crate::decimal;
+use crate::{
+    collections::{keyword_list_order, keyword_list_tag, Tuple},
+    date_time::DateTime,
+    error::ConversionError,
+    eval_options::{map_keys, tuples},
+    fn_ptr::FnPtrHandle,
+    fun,
+    opaque::{Pid, Port, Reference},
+};
+
+mod atoms {
+    rustler::atoms! {
+        blob
+    }
+}
+
+/// Kinds of Elixir map keys, recorded in the tag of the object maps they are converted to so that
+/// `MapKeys::Atoms` can return the keys as they were passed.
+///
+/// Scripts can read and set the tag of a map, and copies of the map keep it, so the recorded kinds
+/// are only a hint: tags other than a combination of these kinds are ignored, and keys are only
+/// returned as integers or atoms if they are the names of integers or existing atoms. Maps
+/// converted from keyword lists are tagged with the order of their keys instead, as negative tags.
+const STRING_KEYS: i32 = 1;
+const ATOM_KEYS: i32 = 2;
+const INTEGER_KEYS: i32 = 4;
+
+/// How the keys of object maps are returned to Elixir.
+#[derive(NifUnitEnum, Clone, Copy, Default)]
+pub enum MapKeys {
+    /// Keys are strings.
+    #[default]
+    Strings,
+    /// Keys are returned as they were passed: atom keys as atoms, integer keys as integers and
+    /// string keys as strings. Keys of maps created by scripts are strings.
+    Atoms,
+}
+
+/// How Elixir tuples are passed to scripts.
+#[derive(NifUnitEnum, Clone, Copy, Default)]
+pub enum Tuples {
+    /// Tuples are arrays, returned as lists.
+    #[default]
+    Arrays,
+    /// Tuples are `Tuple` values, which scripts can index like arrays and are returned as tuples.
+    Tagged,
+}
+
+/// Convert a value with the map keys of the evaluation running on the current thread.
+pub fn from_dynamic(env: Env, value: Dynamic) -> Result<Term, ConversionError> {
+    convert(env, value, None, map_keys())
+}
+
+/// Convert a value returned by an evaluation. If it is the evaluation of `ast`, function
+/// pointers to the functions it defines can be called later.
+pub fn from_dynamic_with<'a>(
+    env: Env<'a>,
+    value: Dynamic,
+    ast: Option<&AST>,
+    map_keys: MapKeys,
+) -> Result<Term<'a>, ConversionError> {
+    convert(env, value, ast, map_keys)
+}
+
+fn convert<'a>(
+    env: Env<'a>,
+    value: Dynamic,
+    ast: Option<&AST>,
+    map_keys: MapKeys,
+) -> Result<Term<'a>, ConversionError> {
+    let term = match value.type_name() {
         "()" => rustler::types::atom::nil().to_term(env),
         "i64" => value.cast::<i64>().encode(env),
         "f64" => value.cast::<f64>().encode(env),
         "bool" => value.cast::<bool>().encode(env),
         "string" => value.cast::<String>().encode(env),
         "char" => value.cast::<char>().to_string().encode(env),
+        "blob" => {
+            let blob = value.cast::<Blob>();
+            let mut binary = NewBinary::new(env, blob.len());
+            binary.as_mut_slice().copy_from_slice(&blob);
+
+            Binary::from(binary).encode(env)
+        }
         "array" => value
             .cast::<Vec<Dynamic>>()
             .into_iter()
-            .map(|v| from_dynamic(env, v))
-            .collect::<Vec<Term>>()
+            .enumerate()
+            .map(|(i, v)| {
+                convert(env, v, ast, map_keys).map_err(|err| err.within(format!("[{}]", i)))
+            })
+            .collect::<Result<Vec<Term>, ConversionError>>()?
             .encode(env),
         "map" => {
-            let mut map: HashMap<String, Term> = HashMap::new();
-            for (k, v) in value.cast::<rhai::Map>() {
-                map.insert(k.into(), from_dynamic(env, v));
+            let tag = value.tag();
+            let map = value.cast::<rhai::Map>();
+
+            if let Some(order) = keyword_list_order(tag) {
+                return keyword_list(env, map, &order, ast, map_keys);
+            }
+
+            let mut pairs: Vec<(Term, Term)> = Vec::new();
+            let key_kinds = key_kinds(tag);
+
+            for (k, v) in map {
+                let v = convert(env, v, ast, map_keys)
+                    .map_err(|err| err.within(format!("[{:?}]", k)))?;
+                let k = match map_keys {
+                    MapKeys::Atoms => original_key(env, &k, key_kinds),
+                    MapKeys::Strings => k.as_str().encode(env),
+                };
+
+                pairs.push((k, v));
             }
-            map.encode(env)
+
+            // Keys are unique, as property names are, and so are the keys they convert to.
+            Term::map_from_pairs(env, &pairs).map_err(|_| {
+                ConversionError::new("Cannot convert an object map to an Elixir map".to_string())
+            })?
         }
-        _ => ().encode(env),
-    }
+        "Fn" => {
+            let fn_ptr = value.cast::<FnPtr>();
+
+            match fun::from_fn_ptr(env, &fn_ptr) {
+                Some(fun) => fun,
+                None => FnPtrHandle::new(env, fn_ptr, ast).encode(env),
+            }
+        }
+        #[cfg(feature = "decimal")]
+        "decimal" => decimal::encode(env, value.cast::<rust_decimal::Decimal>()),
+        "timestamp" => DateTime::from_instant(value.cast::<Instant>()).encode(env),
+        _ if value.is::<DateTime>() => value.cast::<DateTime>().encode(env),
+        _ if value.is::<Tuple>() => {
+            let items = value
+                .cast::<Tuple>()
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    convert(env, v, ast, map_keys).map_err(|err| err.within(format!("[{}]", i)))
+                })
+                .collect::<Result<Vec<Term>, ConversionError>>()?;
+
+            make_tuple(env, &items)
+        }
+        _ if value.is::<Pid>() => value.cast::<Pid>().decode(env)?,
+        _ if value.is::<Reference>() => value.cast::<Reference>().decode(env)?,
+        _ if value.is::<Port>() => value.cast::<Port>().decode(env)?,
+        type_name => {
+            return Err(ConversionError::new(format!(
+                "Cannot convert a Rhai value of type '{}' to an Elixir term",
+                type_name
+            )))
+        }
+    };
+
+    Ok(term)
 }
 
-pub fn to_dynamic<'a>(env: Env<'a>, term: &Term<'a>) -> Dynamic {
-    match Term::get_type(*term) {
-        TermType::Binary => term
-            .decode::<String>()
-            .map(Dynamic::from)
-            .expect("get_type() returned Binary but could not decode as string."),
+/// Convert a term with the tuple strategy of the evaluation running on the current thread.
+pub fn to_dynamic<'a>(env: Env<'a>, term: &Term<'a>) -> Result<Dynamic, ConversionError> {
+    to_dynamic_with(env, term, tuples())
+}
+
+pub fn to_dynamic_with<'a>(
+    env: Env<'a>,
+    term: &Term<'a>,
+    tuples: Tuples,
+) -> Result<Dynamic, ConversionError> {
+    let value = match Term::get_type(*term) {
+        TermType::Binary => {
+            let binary = term.decode::<Binary>().map_err(|_| unsupported(term))?;
+
+            // Binaries that are not valid UTF-8 cannot be Rhai strings, so they become BLOBs.
+            match std::str::from_utf8(binary.as_slice()) {
+                Ok(string) => Dynamic::from(string.to_string()),
+                Err(_) => Dynamic::from_blob(binary.as_slice().to_vec()),
+            }
+        }
 
         TermType::Atom => term
             .decode::<bool>()
@@ -45,50 +201,261 @@ pub fn to_dynamic<'a>(env: Env<'a>, term: &Term<'a>) -> Dynamic {
                     term.atom_to_string().map(Dynamic::from)
                 }
             })
-            .expect("get_type() returned Atom but could not decode as string, boolean or empty."),
-        TermType::Fun => Dynamic::from(()),
+            .map_err(|_| unsupported(term))?,
         TermType::List => {
-            let items: Vec<Dynamic> = term
-                .decode::<Vec<Term>>()
-                .expect("get_type() returned List but could not decode as list.")
+            let items = term.decode::<Vec<Term>>().map_err(|_| {
+                ConversionError::new(format!("Cannot convert an improper list {:?}", term))
+            })?;
+
+            // Keyword lists are object maps, tagged with the order of their keys so that they are
+            // returned in the same order.
+            if let Some(pairs) = keyword_pairs(&items) {
+                let keys = pairs.iter().map(|(k, _)| k.clone()).collect();
+                let mut object_map = rhai::Map::new();
+
+                for (k, v) in pairs {
+                    let v = to_dynamic_with(env, &v, tuples)
+                        .map_err(|err| err.within(format!("[{:?}]", k)))?;
+
+                    object_map.insert(k.into(), v);
+                }
+
+                let mut object_map = Dynamic::from(object_map);
+
+                object_map.set_tag(keyword_list_tag(keys));
+
+                return Ok(object_map);
+            }
+
+            let items: Vec<Dynamic> = items
                 .iter()
-                .map(|item| to_dynamic(env, item))
-                .collect();
+                .enumerate()
+                .map(|(i, item)| {
+                    to_dynamic_with(env, item, tuples).map_err(|err| err.within(format!("[{}]", i)))
+                })
+                .collect::<Result<_, _>>()?;
 
             Dynamic::from_array(items)
         }
         TermType::Map => {
+            if let Ok(handle) = term.decode::<FnPtrHandle>() {
+                return Ok(Dynamic::from(handle.resource.fn_ptr.clone()));
+            }
+
+            if let Some(date_time) = DateTime::from_term(*term) {
+                return Ok(Dynamic::from(date_time?));
+            }
+
+            #[cfg(feature = "decimal")]
+            if let Some(decimal) = decimal::from_term(*term) {
+                return Ok(Dynamic::from_decimal(decimal?));
+            }
+
             let mut object_map = rhai::Map::new();
+            let mut key_kinds = 0;
 
             for (k, v) in term
-                .decode::<HashMap<String, Term>>()
-                .expect("get_type() returned Map but could not decod Hashmap.")
+                .decode::<MapIterator>()
+                .map_err(|_| unsupported(term))?
             {
-                object_map.insert(k.into(), to_dynamic(env, &v));
+                let (k, kind) = map_key(k)?;
+                let v = to_dynamic_with(env, &v, tuples)
+                    .map_err(|err| err.within(format!("[{:?}]", k)))?;
+
+                key_kinds |= kind;
+
+                if object_map.insert(k.as_str().into(), v).is_some() {
+                    return Err(ConversionError::new(format!(
+                        "Cannot convert map keys, more than one key converts to {:?}",
+                        k
+                    )));
+                }
             }
-            Dynamic::from(object_map)
+
+            let mut object_map = Dynamic::from(object_map);
+
+            // Maps with string keys only are left untagged, as are the maps scripts create.
+            if key_kinds != STRING_KEYS {
+                object_map.set_tag(key_kinds);
+            }
+            object_map
         }
         TermType::Float => term
             .decode::<f64>()
             .map(Dynamic::from)
-            .expect("get_type() returned Float but could not decode as float."),
-        TermType::Integer => term
-            .decode::<i64>()
-            .map(Dynamic::from)
-            .expect("get_type() returned Integer but could not decode as integer."),
-        TermType::Pid => Dynamic::from(()),
-        TermType::Port => Dynamic::from(()),
-        TermType::Ref => Dynamic::from(()),
+            .map_err(|_| unsupported(term))?,
+        TermType::Integer => match term.decode::<i64>() {
+            Ok(int) => Dynamic::from(int),
+            // Integers out of range become decimals, which can hold up to 96 bits.
+            #[cfg(feature = "decimal")]
+            Err(_) => Dynamic::from_decimal(decimal::from_integer(*term)?),
+            #[cfg(not(feature = "decimal"))]
+            Err(_) => {
+                return Err(ConversionError::new(format!(
+                    "Cannot convert integer {:?}, it does not fit into a Rhai integer",
+                    term
+                )))
+            }
+        },
         TermType::Tuple => {
-            let items: Vec<Dynamic> = get_tuple(*term)
-                .expect("get_type() returned Tuple but could not decode as list.")
+            let items = get_tuple(*term).map_err(|_| unsupported(term))?;
+
+            if let Some(binary) = blob(&items) {
+                return Ok(Dynamic::from_blob(binary.as_slice().to_vec()));
+            }
+
+            let items: Vec<Dynamic> = items
                 .iter()
-                .map(|item| to_dynamic(env, item))
-                .collect();
+                .enumerate()
+                .map(|(i, item)| {
+                    to_dynamic_with(env, item, tuples).map_err(|err| err.within(format!("[{}]", i)))
+                })
+                .collect::<Result<_, _>>()?;
+
+            match tuples {
+                Tuples::Arrays => Dynamic::from(items),
+                Tuples::Tagged => Dynamic::from(Tuple(items)),
+            }
+        }
+        TermType::Fun => Dynamic::from(fun::to_fn_ptr(env, *term)?),
+        // Pids, references and ports are opaque to scripts, which can only pass them back.
+        TermType::Pid => Dynamic::from(Pid::new(*term)),
+        TermType::Ref => Dynamic::from(Reference::new(*term)),
+        TermType::Port => Dynamic::from(Port::new(*term)),
+        TermType::Unknown => return Err(unsupported(term)),
+    };
+
+    Ok(value)
+}
+
+/// The binary of a `{:blob, binary}` tuple, which explicitly marks a binary as a BLOB, even if it
+/// is valid UTF-8.
+fn blob<'a>(items: &[Term<'a>]) -> Option<Binary<'a>> {
+    match items {
+        [tag, binary] if tag.decode::<Atom>().is_ok_and(|tag| tag == atoms::blob()) => {
+            binary.decode::<Binary>().ok()
+        }
+        _ => None,
+    }
+}
+
+/// The pairs of a keyword list, if the list is one. Empty lists are arrays, and so are lists of
+/// `{:blob, binary}` tuples and lists with duplicate keys, which object maps cannot hold.
+fn keyword_pairs<'a>(items: &[Term<'a>]) -> Option<Vec<(String, Term<'a>)>> {
+    if items.is_empty() {
+        return None;
+    }
+
+    let pairs: Vec<(String, Term)> = items
+        .iter()
+        .map(|item| {
+            let pair = get_tuple(*item).ok()?;
+
+            match pair.as_slice() {
+                [key, value] if blob(&pair).is_none() => Some((key.atom_to_string().ok()?, *value)),
+                _ => None,
+            }
+        })
+        .collect::<Option<_>>()?;
+
+    let mut keys = HashSet::new();
+
+    pairs
+        .iter()
+        .all(|(k, _)| keys.insert(k.as_str()))
+        .then_some(pairs)
+}
+
+/// The keyword list an object map was converted from, with its keys in their original order,
+/// followed by the keys set by scripts.
+fn keyword_list<'a>(
+    env: Env<'a>,
+    mut map: rhai::Map,
+    order: &[String],
+    ast: Option<&AST>,
+    map_keys: MapKeys,
+) -> Result<Term<'a>, ConversionError> {
+    let mut pairs: Vec<_> = order
+        .iter()
+        .filter_map(|k| map.remove_entry(k.as_str()))
+        .collect();
+
+    pairs.extend(map);
+
+    Ok(pairs
+        .into_iter()
+        .map(|(k, v)| {
+            let v =
+                convert(env, v, ast, map_keys).map_err(|err| err.within(format!("[{:?}]", k)))?;
+            // Keys set by scripts must already be atoms, as no atoms are created.
+            let k = match Atom::try_from_bytes(env, k.as_bytes()) {
+                Ok(Some(atom)) => atom,
+                _ => {
+                    return Err(ConversionError::new(format!(
+                        "Cannot convert keyword list key {:?}, it is not an existing atom",
+                        k
+                    )))
+                }
+            };
+
+            Ok((k, v))
+        })
+        .collect::<Result<Vec<(Atom, Term)>, ConversionError>>()?
+        .encode(env))
+}
+
+/// The property name of an Elixir map key, along with its kind.
+fn map_key(key: Term) -> Result<(String, i32), ConversionError> {
+    match key.get_type() {
+        TermType::Binary => key.decode::<String>().ok().map(|k| (k, STRING_KEYS)),
+        TermType::Atom => key.atom_to_string().ok().map(|k| (k, ATOM_KEYS)),
+        TermType::Integer => key
+            .decode::<i128>()
+            .ok()
+            .map(|int| (int.to_string(), INTEGER_KEYS)),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        ConversionError::new(format!(
+            "Cannot convert map key {:?}, only string, atom and integer keys are supported",
+            key
+        ))
+    })
+}
+
+/// The kinds of keys recorded in the tag of a map, or `STRING_KEYS` if the tag is not a
+/// combination of kinds, as for maps created by scripts or tagged by them.
+fn key_kinds(tag: i32) -> i32 {
+    if tag > 0 && tag & !(STRING_KEYS | ATOM_KEYS | INTEGER_KEYS) == 0 {
+        tag
+    } else {
+        STRING_KEYS
+    }
+}
 
-            Dynamic::from(items)
+/// The Elixir key of a property, according to the kinds of keys recorded in the tag of its map.
+/// In maps passed with string keys along with atom or integer keys, the names of integers and
+/// existing atoms are taken to be integer and atom keys.
+fn original_key<'a>(env: Env<'a>, key: &str, key_kinds: i32) -> Term<'a> {
+    if key_kinds & INTEGER_KEYS != 0 {
+        if let Some(int) = key
+            .parse::<i128>()
+            .ok()
+            .filter(|int| int.to_string() == key)
+        {
+            return int.encode(env);
         }
+    }
 
-        TermType::Unknown => Dynamic::from(()),
+    if key_kinds & ATOM_KEYS != 0 {
+        if let Ok(Some(atom)) = Atom::try_from_bytes(env, key.as_bytes()) {
+            return atom.encode(env);
+        }
     }
+
+    key.encode(env)
+}
+
+fn unsupported(term: &Term) -> ConversionError {
+    ConversionError::new(format!("Cannot convert {:?} to a Rhai value", term))
 }
@@ -0,0 +1,67 @@
+defmodule Rhai.CancelTokenTest do
+  use ExUnit.Case
+
+  alias Rhai.{CancelToken, Engine, Scope}
+
+  describe "cancel/2" do
+    test "should mark the token as cancelled" do
+      token = CancelToken.new()
+
+      refute CancelToken.cancelled?(token)
+      assert CancelToken.cancelled?(CancelToken.cancel(token))
+    end
+
+    test "should stop a running evaluation with the reason" do
+      engine = Engine.new()
+      token = CancelToken.new()
+
+      task = Task.async(fn -> Engine.eval(engine, "loop {}", cancel_token: token) end)
+      Process.sleep(50)
+      CancelToken.cancel(token, {:shutdown, self()})
+
+      assert {:error, {:terminated, _, {:shutdown, pid}}} = Task.await(task)
+      assert pid == self()
+    end
+
+    test "should stop a running function call" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn spin() { loop {} }")
+      token = CancelToken.new()
+
+      task =
+        Task.async(fn ->
+          Engine.call_fn(engine, Scope.new(), ast, "spin", [], cancel_token: token)
+        end)
+
+      Process.sleep(50)
+      CancelToken.cancel(token)
+
+      assert {:error, {:terminated, _, :cancelled}} = Task.await(task)
+    end
+
+    test "should stop an evaluation waiting for a registered function" do
+      pid = spawn_link(fn -> Process.sleep(:infinity) end)
+      engine = Engine.register_fn(Engine.new(), "slow", 0, pid)
+      token = CancelToken.new()
+
+      task = Task.async(fn -> Engine.eval(engine, "slow()", cancel_token: token) end)
+      Process.sleep(50)
+      CancelToken.cancel(token, :stop)
+
+      assert {:ok, {:error, {:terminated, _, :stop}}} = Task.yield(task, 1_000)
+    end
+
+    test "should stop evaluations started with a cancelled token and keep the first reason" do
+      token = CancelToken.new() |> CancelToken.cancel(:first) |> CancelToken.cancel(:second)
+
+      assert {:error, {:terminated, _, :first}} =
+               Engine.run_with_scope(Engine.new(), Scope.new(), "let x = 1;", cancel_token: token)
+    end
+
+    test "should not affect evaluations without the token" do
+      CancelToken.new() |> CancelToken.cancel()
+
+      assert {:ok, 2} = Engine.eval(Engine.new(), "1 + 1")
+    end
+  end
+end
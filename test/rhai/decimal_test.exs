@@ -0,0 +1,45 @@
+defmodule Rhai.DecimalTest do
+  use ExUnit.Case
+
+  alias Rhai.{Engine, Scope}
+
+  @moduletag :decimal
+
+  # The decimal package is not a dependency, so structs are built by hand.
+  defp decimal(sign, coef, exp), do: %{__struct__: Decimal, sign: sign, coef: coef, exp: exp}
+
+  describe "type conversion" do
+    test "should convert Decimal structs to rhai decimals and back" do
+      price = decimal(1, 1999, -2)
+      scope = Scope.push(Scope.new(), "price", price)
+
+      assert {:ok, "decimal"} = Engine.eval_with_scope(Engine.new(), scope, "type_of(price)")
+      assert {:ok, ^price} = Engine.eval_with_scope(Engine.new(), scope, "price")
+
+      assert {:ok, %{__struct__: Decimal, sign: -1, coef: 5997, exp: -2}} =
+               Engine.eval_with_scope(Engine.new(), scope, "-price * 3")
+    end
+
+    test "should convert Decimal structs with positive exponents" do
+      scope = Scope.push(Scope.new(), "a", decimal(1, 12, 3))
+
+      assert {:ok, %{__struct__: Decimal, sign: 1, coef: 12_000, exp: 0}} =
+               Engine.eval_with_scope(Engine.new(), scope, "a")
+    end
+
+    test "should convert integers that do not fit into 64 bits to decimals" do
+      scope = Scope.push(Scope.new(), "a", 9_223_372_036_854_775_808)
+
+      assert {:ok, "decimal"} = Engine.eval_with_scope(Engine.new(), scope, "type_of(a)")
+
+      assert {:ok, %{__struct__: Decimal, sign: 1, coef: 9_223_372_036_854_775_809, exp: 0}} =
+               Engine.eval_with_scope(Engine.new(), scope, "a + 1")
+    end
+
+    test "should not convert values that do not fit into decimals" do
+      for value <- [1_267_650_600_228_229_401_496_703_205_376, decimal(1, :NaN, 0), decimal(1, 1, -29)] do
+        assert_raise ArgumentError, fn -> Scope.push(Scope.new(), "a", value) end
+      end
+    end
+  end
+end
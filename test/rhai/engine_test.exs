@@ -1,7 +1,7 @@
 defmodule Rhai.EngineTest do
   use ExUnit.Case
 
-  alias Rhai.{AST, Engine, Scope}
+  alias Rhai.{AST, CancelToken, Engine, Scope}
 
   describe "new/0" do
     test "should create a new engine" do
@@ -43,6 +43,97 @@ defmodule Rhai.EngineTest do
                result
                """)
     end
+
+    test "should resolve file modules from the base path" do
+      engine =
+        Engine.set_module_resolvers(Engine.new(), [{:file, base_path: "test/fixtures/modules"}])
+
+      assert {:ok, "Hello, Rhai"} =
+               Engine.eval(engine, ~s|import "greeter" as g; g::greet("Rhai")|)
+    end
+
+    test "should resolve file modules with the extension" do
+      engine =
+        Engine.set_module_resolvers(Engine.new(), [
+          {:file, base_path: "test/fixtures/modules", extension: "module"}
+        ])
+
+      assert {:ok, 42} = Engine.eval(engine, ~s|import "answer" as a; a::VALUE|)
+    end
+
+    test "should not resolve file modules outside of the sandbox" do
+      sandboxed = [{:file, base_path: "test/fixtures/modules", sandbox: true}]
+      engine = Engine.set_module_resolvers(Engine.new(), sandboxed)
+
+      assert {:ok, "Hello, Rhai"} =
+               Engine.eval(engine, ~s|import "greeter" as g; g::greet("Rhai")|)
+
+      assert {:error, {:module_not_found, _}} = Engine.eval(engine, ~s|import "../script" as m;|)
+
+      assert {:error, {:module_not_found, _}} =
+               Engine.eval(engine, ~s|import "#{File.cwd!()}/test/fixtures/script" as m;|)
+
+      assert {:ok, _} =
+               Engine.new()
+               |> Engine.set_module_resolvers([{:file, base_path: "test/fixtures/modules"}])
+               |> Engine.eval(~s|import "../script" as m;|)
+    end
+
+    test "should only import the allowed module paths" do
+      resolver = Rhai.StaticModuleResolver.new()
+      opts = [allow: ["std/*", "tenant/**"], deny: ["tenant/internal/**"]]
+      engine = Engine.set_module_resolvers(Engine.new(), [resolver], opts)
+
+      for path <- ["std/math", "std/nested/math", "tenant/a/b", "tenant/internal/secret"] do
+        {:ok, _} =
+          Rhai.StaticModuleResolver.add(resolver, engine, path, "export const VALUE = 42;")
+      end
+
+      assert {:ok, 42} = Engine.eval(engine, ~s|import "std/math" as m; m::VALUE|)
+      assert {:ok, 42} = Engine.eval(engine, ~s|import "tenant/a/b" as m; m::VALUE|)
+
+      assert {:error, {:module_forbidden, message}} =
+               Engine.eval(engine, ~s|import "std/nested/math" as m;|)
+
+      assert message =~ "Module 'std/nested/math' is not allowed"
+      assert message =~ ~r/line 1, position \d+/
+
+      assert {:error, {:module_forbidden, _}} =
+               Engine.eval(engine, ~s|import "tenant/internal/secret" as m;|)
+    end
+
+    test "should not import paths with relative segments" do
+      resolver = Rhai.StaticModuleResolver.new()
+      opts = [allow: ["tenant/**"], deny: ["tenant/internal/**"]]
+      engine = Engine.set_module_resolvers(Engine.new(), [resolver], opts)
+
+      for path <- ["secret", "tenant/internal/secret", "tenant/x/../internal/secret"] do
+        {:ok, _} = Rhai.StaticModuleResolver.add(resolver, engine, path, "export const V = 1;")
+      end
+
+      for path <- [
+            "tenant/../secret",
+            "tenant/x/../internal/secret",
+            "tenant/./internal/secret",
+            "tenant//internal/secret",
+            "tenant\\\\..\\\\secret",
+            "/tenant/a"
+          ] do
+        assert {:error, {:module_forbidden, _}} =
+                 Engine.eval(engine, ~s|import "#{path}" as m;|)
+      end
+    end
+
+    test "should return a module not found error for forbidden paths if configured" do
+      engine =
+        Engine.set_module_resolvers(Engine.new(), [:file],
+          deny: ["**"],
+          forbidden_error: :module_not_found
+        )
+
+      assert {:error, {:module_not_found, _}} =
+               Engine.eval(engine, ~s|import "#{File.cwd!()}/test/fixtures/script" as m;|)
+    end
   end
 
   describe "register_global_module/2" do
@@ -68,7 +159,7 @@ defmodule Rhai.EngineTest do
     end
 
     test "should return error if the module is not found" do
-      assert {:error, {:runtime, _}} =
+      assert {:error, {:runtime, _, _}} =
                Engine.new()
                |> Engine.register_global_module("non_existing_module")
     end
@@ -119,7 +210,7 @@ defmodule Rhai.EngineTest do
     test "should return error if the module is not found" do
       engine = Engine.new()
 
-      assert {:error, {:runtime, _}} =
+      assert {:error, {:runtime, _, _}} =
                Engine.register_static_module(engine, "plugin", "non_existing_module")
     end
   end
@@ -144,6 +235,83 @@ defmodule Rhai.EngineTest do
     end
   end
 
+  describe "register_global_script_module/3" do
+    test "should register the functions of a script source into the global namespace" do
+      assert {:ok, engine} =
+               Engine.register_global_script_module(Engine.new(), "fn double(x) { x * 2 }")
+
+      assert {:ok, 42} = Engine.eval(engine, "double(21)")
+    end
+
+    test "should register the functions of an AST into the global namespace" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn double(x) { x * 2 }")
+
+      assert {:ok, engine} = Engine.register_global_script_module(engine, ast)
+      assert {:ok, 42} = Engine.eval(engine, "double(21)")
+    end
+
+    test "should return an error if the script fails to compile or evaluate" do
+      engine = Engine.new()
+
+      assert {:error, {:parsing, _}} =
+               Engine.register_global_script_module(engine, "fn broken( {")
+
+      assert {:error, {:runtime, _, "boom"}} =
+               Engine.register_global_script_module(engine, ~s|throw "boom";|)
+    end
+  end
+
+  describe "register_static_script_module/4" do
+    test "should register a script into a namespace" do
+      script = "export const ANSWER = 21; fn double(x) { x * 2 }"
+
+      assert {:ok, engine} =
+               Engine.register_static_script_module(Engine.new(), "helpers", script)
+
+      assert {:ok, 42} = Engine.eval(engine, "helpers::double(helpers::ANSWER)")
+      assert {:error, {:function_not_found, _}} = Engine.eval(engine, "double(21)")
+    end
+
+    test "should accept evaluation options" do
+      assert {:error, {:timeout, _}} =
+               Engine.register_static_script_module(Engine.new(), "spin", "loop {}", timeout: 50)
+    end
+  end
+
+  describe "register_constants_module/4" do
+    test "should register constants visible inside script functions" do
+      constants = %{"max_retries" => 3, db: %{"host" => "localhost"}}
+
+      assert {:ok, engine} = Engine.register_constants_module(Engine.new(), "config", constants)
+
+      assert {:ok, 6} =
+               Engine.eval(engine, "fn retries() { config::max_retries * 2 } retries()")
+
+      assert {:ok, "localhost"} = Engine.eval(engine, "config::db.host")
+    end
+
+    test "should not allow assigning the constants" do
+      {:ok, engine} = Engine.register_constants_module(Engine.new(), "config", max_retries: 3)
+
+      assert {:error, _} = Engine.eval(engine, "config::max_retries = 4;")
+    end
+
+    test "should return an error if a value cannot be converted" do
+      assert {:error, {:conversion, _}} =
+               Engine.register_constants_module(Engine.new(), "config", list: [1 | 2])
+    end
+
+    test "should convert tuples with the tuple strategy" do
+      constants = %{"pair" => {1, 2}}
+
+      {:ok, engine} =
+        Engine.register_constants_module(Engine.new(), "config", constants, tuples: :tagged)
+
+      assert {:ok, {1, 2}} = Engine.eval(engine, "config::pair")
+    end
+  end
+
   describe "register_custom_operator/3" do
     test "should register a custom operator" do
       assert {:ok, engine} =
@@ -199,6 +367,299 @@ defmodule Rhai.EngineTest do
     end
   end
 
+  describe "register_fn/5" do
+    test "should call an Elixir process from a script" do
+      pid =
+        spawn_link(fn ->
+          receive do
+            {:rhai_call, callback, "add", [a, b]} -> Rhai.Callback.reply(callback, {:ok, a + b})
+          end
+        end)
+
+      assert {:ok, 42} =
+               Engine.new()
+               |> Engine.register_fn("add", 2, pid)
+               |> Engine.eval("add(40, 2)")
+    end
+
+    test "should return a runtime error if the process replies with an error" do
+      pid =
+        spawn_link(fn ->
+          receive do
+            {:rhai_call, callback, "fail", []} -> Rhai.Callback.reply(callback, {:error, "boom"})
+          end
+        end)
+
+      assert {:error, {:runtime, "Runtime error: boom" <> _, "boom"}} =
+               Engine.new()
+               |> Engine.register_fn("fail", 0, pid)
+               |> Engine.eval("fail()")
+    end
+
+    test "should return a runtime error if the process does not reply in time" do
+      pid = spawn_link(fn -> Process.sleep(:infinity) end)
+
+      assert {:error, {:runtime, _, "Function 'slow' timed out after 10 ms"}} =
+               Engine.new()
+               |> Engine.register_fn("slow", 0, pid, timeout: 10)
+               |> Engine.eval("slow()")
+    end
+  end
+
+  describe "Elixir functions" do
+    test "should be callable from scripts" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn apply(f, x) { f.call(x) + call(f, x) }")
+
+      assert {:ok, 42} = Engine.call_fn(engine, Scope.new(), ast, "apply", [&(&1 * 3), 7])
+    end
+
+    test "should be usable with array functions" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn map_items(items, f) { items.map(f) }")
+
+      assert {:ok, ["A", "B"]} =
+               Engine.call_fn(engine, Scope.new(), ast, "map_items", [
+                 ["a", "b"],
+                 &String.upcase/1
+               ])
+    end
+
+    test "should be callable from scope values" do
+      scope = Scope.new() |> Scope.push("greet", fn name -> "hello " <> name end)
+
+      assert {:ok, "hello rhai"} =
+               Engine.eval_with_scope(Engine.new(), scope, ~s|greet.call("rhai")|)
+    end
+
+    test "should be returned as Elixir functions" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn id(f) { f }")
+      fun = &String.upcase/1
+
+      assert {:ok, ^fun} = Engine.call_fn(engine, Scope.new(), ast, "id", [fun])
+    end
+
+    test "should return a runtime error if the function raises" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn run(f) { f.call() }")
+
+      assert {:error, {:runtime, _, message}} =
+               Engine.call_fn(engine, Scope.new(), ast, "run", [fn -> raise "boom" end])
+
+      assert message =~ "(RuntimeError) boom"
+    end
+
+    test "should stop waiting for the function at the deadline of the evaluation" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn run(f) { f.call() }")
+      slow = fn -> Process.sleep(:infinity) end
+
+      assert {:error, {:timeout, _}} =
+               Engine.call_fn(engine, Scope.new(), ast, "run", [slow], timeout: 50)
+    end
+
+    test "should wait for the function as long as the evaluation has no timeout" do
+      scope = Scope.push(Scope.new(), "slow", fn -> Process.sleep(5_100) && :done end)
+
+      assert {:ok, "done"} = Engine.eval_with_scope(Engine.new(), scope, "slow.call()")
+    end
+  end
+
+  describe "set_output/2" do
+    test "should send print and debug output to a process" do
+      engine = Engine.new() |> Engine.set_output(self())
+
+      assert {:ok, _} = Engine.eval(engine, ~s|print("hello"); debug("world")|)
+
+      assert_receive {:rhai_output, {:print, "hello"}}
+      assert_receive {:rhai_output, {:debug, ~s("world"), nil, {1, 17}}}
+    end
+
+    test "should include the source of the script in debug output" do
+      engine = Engine.new() |> Engine.set_output(self())
+      {:ok, ast} = Engine.compile(engine, "debug(1)")
+      ast = AST.set_source(ast, "my_script")
+
+      assert {:ok, _} = Engine.eval_ast(engine, ast)
+
+      assert_receive {:rhai_output, {:debug, "1", "my_script", {1, 1}}}
+    end
+
+    test "should send the output of a loop in order" do
+      engine = Engine.new() |> Engine.set_output(self())
+
+      assert {:ok, _} = Engine.eval(engine, "for i in 0..1000 { print(i) }")
+
+      for i <- 0..999 do
+        text = Integer.to_string(i)
+        assert_receive {:rhai_output, {:print, ^text}}
+      end
+    end
+  end
+
+  describe "eval/3 with captured output" do
+    test "should return the output alongside the result" do
+      engine = Engine.new() |> Engine.set_output(self())
+
+      assert {{:ok, 42}, [{:print, "hello"}, {:debug, "2", nil, {1, 17}}]} =
+               Engine.eval(engine, ~s|print("hello"); debug(2); 42|, capture_output: true)
+
+      refute_receive {:rhai_output, _}
+    end
+
+    test "should return the output alongside errors" do
+      assert {{:error, {:runtime, _, _}}, [{:print, "before"}]} =
+               Engine.eval(Engine.new(), ~s|print("before"); throw "boom"|, capture_output: true)
+    end
+
+    test "should capture the output of run" do
+      assert {:ok, [{:print, "hello"}]} =
+               Engine.run(Engine.new(), ~s|print("hello")|, capture_output: true)
+    end
+  end
+
+  describe "eval/3 with detailed errors" do
+    test "should return the position of the error" do
+      assert {:error,
+              %{
+                type: :runtime,
+                message: "Runtime error: boom",
+                line: 2,
+                column: 1,
+                source: nil,
+                function_stack: [],
+                inner: nil
+              }} = Engine.eval(Engine.new(), "let x = 1;\nthrow \"boom\"", detailed_errors: true)
+    end
+
+    test "should return the chain of function calls" do
+      engine = Engine.new()
+
+      {:ok, ast} =
+        Engine.compile(engine, """
+        fn inner() { throw "boom" }
+        fn outer() { inner() }
+        outer()
+        """)
+
+      ast = AST.set_source(ast, "my_script")
+
+      assert {:error,
+              %{
+                type: :in_function_call,
+                message: "Error in call to function 'outer'",
+                line: 3,
+                column: 1,
+                source: "my_script",
+                function_stack: ["outer", "inner"],
+                inner: %{
+                  type: :in_function_call,
+                  line: 2,
+                  column: 14,
+                  source: "my_script",
+                  function_stack: ["inner"],
+                  inner: %{
+                    type: :runtime,
+                    message: "Runtime error: boom",
+                    line: 1,
+                    column: 14,
+                    source: "my_script",
+                    function_stack: [],
+                    inner: nil
+                  }
+                }
+              }} = Engine.eval_ast(engine, ast, detailed_errors: true)
+    end
+
+    test "should include the source of the AST in errors of call_fn/6" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, ~s|fn fail() { throw "boom" }|)
+      ast = AST.set_source(ast, "my_script")
+
+      assert {:error, %{source: "my_script"}} =
+               Engine.call_fn(engine, Scope.new(), ast, "fail", [], detailed_errors: true)
+    end
+
+    test "should include the reason of cancelled evaluations" do
+      token = CancelToken.new() |> CancelToken.cancel(:stop)
+
+      assert {:error, %{type: :terminated, reason: :stop}} =
+               Engine.eval(Engine.new(), "1", cancel_token: token, detailed_errors: true)
+    end
+
+    test "should include the thrown value of runtime errors" do
+      assert {:error, %{type: :runtime, value: %{"code" => 42}}} =
+               Engine.eval(Engine.new(), "throw \#{code: 42}", detailed_errors: true)
+    end
+
+    test "should detail parse errors" do
+      assert {:error,
+              %{type: :parsing, kind: :unexpected_input, message: message, line: 1, column: 12}} =
+               Engine.eval(Engine.new(), "let x = 1 +;", detailed_errors: true)
+
+      assert message == "Syntax error: Unexpected ';'"
+    end
+
+    test "should detail errors raised outside of the evaluation" do
+      assert {:error, %{type: :conversion, line: nil, function_stack: []}} =
+               Engine.eval(Engine.new(), "1..2", detailed_errors: true)
+    end
+  end
+
+  describe "eval_async/3" do
+    test "should reply with the result" do
+      ref = Engine.eval_async(Engine.new(), "40 + 2")
+
+      assert_receive {^ref, {:ok, 42}}
+    end
+
+    test "should reply with errors" do
+      ref = Engine.eval_async(Engine.new(), ~s|throw "boom"|)
+
+      assert_receive {^ref, {:error, {:runtime, _, _}}}
+    end
+
+    test "should not block the calling process" do
+      token = CancelToken.new()
+      ref = Engine.eval_async(Engine.new(), "loop {}", cancel_token: token)
+
+      send(self(), :ping)
+      assert_receive :ping
+      refute_received {^ref, _}
+
+      CancelToken.cancel(token)
+
+      assert_receive {^ref, {:error, {:terminated, _, :cancelled}}}
+    end
+
+    test "should reply with the captured output" do
+      ref = Engine.eval_async(Engine.new(), ~s|print("hello"); 1|, capture_output: true)
+
+      assert_receive {^ref, {{:ok, 1}, [{:print, "hello"}]}}
+    end
+  end
+
+  describe "call_fn_async/6" do
+    test "should reply with the result of the function" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn add(a, b) { a + b }")
+
+      ref = Engine.call_fn_async(engine, Scope.new(), ast, "add", [40, 2])
+
+      assert_receive {^ref, {:ok, 42}}
+    end
+
+    test "should reply with conversion errors" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn id(x) { x }")
+
+      ref = Engine.call_fn_async(engine, Scope.new(), ast, "id", [[1 | 2]])
+
+      assert_receive {^ref, {:error, {:conversion, _}}}
+    end
+  end
+
   describe "compile/2" do
     test "should compile a string into an AST" do
       engine = Engine.new()
@@ -213,6 +674,45 @@ defmodule Rhai.EngineTest do
     end
   end
 
+  describe "compile/3 with detailed errors" do
+    test "should return the kind and position of parse errors" do
+      engine = Engine.new()
+
+      assert {:error, %{type: :parsing, kind: :missing_token, line: 2, column: 1}} =
+               Engine.compile(engine, "fn f() {\n", detailed_errors: true)
+
+      assert {:error, %{type: :parsing, kind: :duplicated_property, line: 1, column: 9}} =
+               Engine.compile(engine, "\#{a: 1, a: 2}", detailed_errors: true)
+    end
+
+    test "should return the kind of errors in the script text" do
+      assert {:error, %{type: :parsing, kind: :unterminated_string, line: 1, column: 9}} =
+               Engine.compile(Engine.new(), ~s|let s = "abc|, detailed_errors: true)
+    end
+
+    test "should return undefined variables in strict variables mode" do
+      assert {:error, %{type: :parsing, kind: :variable_undefined}} =
+               Engine.new()
+               |> Engine.set_strict_variables(true)
+               |> Engine.compile("x + 1", detailed_errors: true)
+    end
+
+    test "should detail errors of the other compile functions" do
+      engine = Engine.new()
+
+      assert {:error, %{type: :parsing, kind: :unexpected_input}} =
+               Engine.compile_expression(engine, "1 +;", detailed_errors: true)
+
+      assert {:error, %{type: :parsing, kind: :unexpected_input}} =
+               Engine.compile_with_scope(engine, Scope.new(), "???", detailed_errors: true)
+    end
+
+    test "should return parse errors as tuples by default" do
+      assert {:error, {:parsing, "Syntax error: " <> _}} =
+               Engine.eval(Engine.new(), "let x = 1 +;")
+    end
+  end
+
   describe "compile_with_scope/3" do
     test "should compile a string into an AST with scope" do
       engine = Engine.new()
@@ -327,6 +827,430 @@ defmodule Rhai.EngineTest do
     end
   end
 
+  describe "eval/1 with blobs" do
+    test "should return a blob as a binary" do
+      engine = Engine.new()
+
+      assert {:ok, <<0xFF, 0xFF, 0xFF>>} = Engine.eval(engine, "blob(3, 0xff)")
+    end
+  end
+
+  describe "eval/1 with date-times" do
+    test "should pass DateTime and NaiveDateTime structs to scripts and back" do
+      engine = Engine.new()
+      date_time = ~U[2024-05-01 12:30:00.123Z]
+      naive_date_time = ~N[2024-05-01 12:30:00]
+      scope = Scope.new() |> Scope.push("a", date_time) |> Scope.push("b", naive_date_time)
+
+      assert {:ok, ^date_time} = Engine.eval_with_scope(engine, scope, "a")
+      assert {:ok, ^naive_date_time} = Engine.eval_with_scope(engine, scope, "b")
+      assert {:ok, "DateTime"} = Engine.eval_with_scope(engine, scope, "type_of(a)")
+    end
+
+    test "should convert DateTime structs to UTC" do
+      date_time = %{~U[2024-05-01 12:30:00Z] | utc_offset: 7200, zone_abbr: "CEST"}
+      scope = Scope.push(Scope.new(), "a", %{date_time | time_zone: "Europe/Paris"})
+
+      assert {:ok, ~U[2024-05-01 10:30:00Z]} = Engine.eval_with_scope(Engine.new(), scope, "a")
+    end
+
+    test "should compare wall-clock times" do
+      engine = Engine.new()
+      scope = Scope.push(Scope.new(), "deadline", ~U[2024-05-01 12:00:00Z])
+
+      assert {:ok, true} = Engine.eval_with_scope(engine, scope, "now() > deadline")
+      assert {:ok, 3600.0} = Engine.eval_with_scope(engine, scope, "deadline + 3600 - deadline")
+
+      script = "let d = deadline; [d.year, d.month, d.day, d.hour]"
+
+      assert {:ok, [2024, 5, 1, 12]} = Engine.eval_with_scope(engine, scope, script)
+
+      assert {:ok, "2024-05-01T12:00:00Z"} =
+               Engine.eval_with_scope(engine, scope, "deadline.to_string()")
+    end
+
+    test "should not compare naive date-times with date-times in UTC" do
+      engine = Engine.new()
+      scope = Scope.new() |> Scope.push("a", ~U[2024-05-01 12:00:00Z])
+      scope = Scope.push(scope, "b", ~N[2024-05-01 12:00:00])
+
+      assert {:ok, [false, true]} = Engine.eval_with_scope(engine, scope, "[a == b, a != b]")
+      assert {:error, {:runtime, message, _}} = Engine.eval_with_scope(engine, scope, "a < b")
+      assert message =~ "Cannot compare a naive date-time"
+      assert {:error, {:runtime, _, _}} = Engine.eval_with_scope(engine, scope, "a - b")
+    end
+
+    test "should return timestamps as DateTime structs" do
+      assert {:ok, %DateTime{time_zone: "Etc/UTC"} = date_time} =
+               Engine.eval(Engine.new(), "timestamp()")
+
+      assert DateTime.diff(DateTime.utc_now(), date_time) in 0..1
+      assert {:ok, 1_700_000_000} = Engine.eval(Engine.new(), "from_unix(1700000000).unix")
+    end
+
+    test "should count leap days of the Gregorian calendar" do
+      engine = Engine.new()
+
+      leap_years = [{2000, true}, {1900, false}, {2100, false}, {2024, true}, {0, true}]
+
+      for {year, leap?} <- leap_years do
+        scope = Scope.push(Scope.new(), "d", NaiveDateTime.new!(year, 2, 28, 0, 0, 0))
+        script = "let next = d + 86400; [next.month, next.day]"
+
+        assert {:ok, if(leap?, do: [2, 29], else: [3, 1])} ==
+                 Engine.eval_with_scope(engine, scope, script)
+      end
+
+      assert {:ok, [2024, 2, 29]} =
+               Engine.eval(engine, "let d = from_unix(1709164800); [d.year, d.month, d.day]")
+    end
+
+    test "should convert date-times before 1970" do
+      engine = Engine.new()
+
+      date_times = [~U[1969-12-31 23:59:59Z], ~U[1600-02-29 12:00:00Z], ~U[0000-01-01 00:00:00Z]]
+
+      for date_time <- date_times do
+        scope = Scope.push(Scope.new(), "d", date_time)
+        script = "[d, d.unix, d.year, d.month, d.day]"
+
+        assert {:ok, [^date_time, unix, year, month, day]} =
+                 Engine.eval_with_scope(engine, scope, script)
+
+        assert unix == DateTime.to_unix(date_time)
+        assert {year, month, day} == {date_time.year, date_time.month, date_time.day}
+      end
+
+      assert {:ok, ~U[1969-12-31 23:59:59Z]} = Engine.eval(engine, "from_unix(-1)")
+    end
+
+    test "should convert date-times around the end of year 9999" do
+      engine = Engine.new()
+      scope = Scope.push(Scope.new(), "d", ~U[9999-12-31 23:59:59.999999Z])
+
+      assert {:ok, ~U[9999-12-31 23:59:59.999999Z]} = Engine.eval_with_scope(engine, scope, "d")
+
+      assert {:ok, %DateTime{year: 10_000, month: 1, day: 1, microsecond: {999_999, 6}}} =
+               Engine.eval_with_scope(engine, scope, "d + 1")
+
+      assert {:ok, [9999, 12, 31]} =
+               Engine.eval(engine, "let d = from_unix(253402300799); [d.year, d.month, d.day]")
+    end
+  end
+
+  describe "eval/3 with map keys" do
+    test "should pass atom and integer keys to scripts as strings" do
+      scope = Scope.push(Scope.new(), "m", %{:id => 1, 2 => "b"})
+
+      script = ~s|[m.id + 1, m["2"]]|
+
+      assert {:ok, [2, "b"]} = Engine.eval_with_scope(Engine.new(), scope, script)
+    end
+
+    test "should return string keys by default" do
+      assert {:ok, %{"id" => 1}} = Engine.eval(Engine.new(), "#{id: 1}")
+    end
+
+    test "should return string keys with map_keys: :atoms" do
+      scope = Scope.push(Scope.new(), "m", %{"id" => 1, "2" => "b"})
+
+      assert {:ok, %{"id" => 2, "2" => "b"}} =
+               Engine.eval_with_scope(Engine.new(), scope, "m.id += 1; m", map_keys: :atoms)
+    end
+
+    test "should return integer keys with map_keys: :atoms" do
+      scope = Scope.push(Scope.new(), "m", %{1 => "a", -2 => "b"})
+
+      assert {:ok, %{1 => "a!", -2 => "b"}} =
+               Engine.eval_with_scope(Engine.new(), scope, ~s|m["1"] += "!"; m|, map_keys: :atoms)
+    end
+
+    test "should return atom keys with map_keys: :atoms" do
+      scope = Scope.push(Scope.new(), "m", %{id: 1, name: "a"})
+
+      assert {:ok, %{id: 1, name: "a", extra: true}} =
+               Engine.eval_with_scope(Engine.new(), scope, "m.extra = true; m", map_keys: :atoms)
+    end
+
+    test "should return mixed atom and integer keys with map_keys: :atoms" do
+      scope = Scope.push(Scope.new(), "m", %{:id => 1, 2 => "b"})
+
+      assert {:ok, %{:id => 1, 2 => "b"}} =
+               Engine.eval_with_scope(Engine.new(), scope, "m", map_keys: :atoms)
+    end
+
+    test "should return string keys for maps created by scripts with map_keys: :atoms" do
+      assert {:ok, %{"id" => 1}} = Engine.eval(Engine.new(), "#{id: 1}", map_keys: :atoms)
+    end
+
+    test "should return string keys for maps tagged by scripts with map_keys: :atoms" do
+      scope = Scope.push(Scope.new(), "m", %{id: 1})
+
+      for tag <- [-1, 8, 99] do
+        script = "m.set_tag(#{tag}); m"
+
+        assert {:ok, %{"id" => 1}} =
+                 Engine.eval_with_scope(Engine.new(), scope, script, map_keys: :atoms)
+      end
+    end
+
+    test "should pass atom keys to Elixir functions with map_keys: :atoms" do
+      scope =
+        Scope.new()
+        |> Scope.push("f", fn %{id: id} -> id end)
+        |> Scope.push("m", %{id: 1})
+
+      assert {:ok, 1} = Engine.eval_with_scope(Engine.new(), scope, "f.call(m)", map_keys: :atoms)
+    end
+
+    test "should not convert integer and string keys converting to the same string" do
+      assert_raise ArgumentError, ~r/more than one key converts to "1"/, fn ->
+        Scope.push(Scope.new(), "m", %{1 => :a, "1" => :b})
+      end
+    end
+
+    test "should not convert atom and string keys converting to the same string" do
+      assert_raise ArgumentError, ~r/more than one key converts to "id"/, fn ->
+        Scope.push(Scope.new(), "m", %{:id => 1, "id" => 2})
+      end
+    end
+
+    test "should not convert other keys" do
+      assert_raise ArgumentError, ~r/Cannot convert map key/, fn ->
+        Scope.push(Scope.new(), "m", %{{1, 2} => 3})
+      end
+    end
+  end
+
+  describe "eval/3 with tuples and keyword lists" do
+    test "should pass tuples as arrays by default" do
+      scope = Scope.push(Scope.new(), "result", {:ok, 42})
+
+      assert {:ok, ["ok", 42]} = Engine.eval_with_scope(Engine.new(), scope, "result")
+    end
+
+    test "should return tagged tuples as tuples" do
+      engine = Engine.new()
+      scope = Scope.push(Scope.new(tuples: :tagged), "result", {:ok, {1, "a"}})
+
+      assert {:ok, {:ok, {1, "a"}}} = Engine.eval_with_scope(engine, scope, "result")
+      assert {:ok, "a"} = Engine.eval_with_scope(engine, scope, "result[1][-1]")
+      assert {:ok, 2} = Engine.eval_with_scope(engine, scope, "result.len")
+      assert {:ok, "Tuple"} = Engine.eval_with_scope(engine, scope, "type_of(result)")
+      assert {:ok, ["ok", {1, "a"}]} = Engine.eval_with_scope(engine, scope, "result.to_array()")
+    end
+
+    test "should keep the tuple strategy of cloned scopes" do
+      scope = Scope.new(tuples: :tagged) |> Scope.push("a", {1}) |> Scope.clone_visible()
+
+      assert {:ok, {2}} = Engine.eval_with_scope(Engine.new(), Scope.push(scope, "b", {2}), "b")
+    end
+
+    test "should convert arguments and Elixir function results with the tuple strategy" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn id(t) { t }")
+
+      assert {:ok, {1, 2}} =
+               Engine.call_fn(engine, Scope.new(), ast, "id", [{1, 2}], tuples: :tagged)
+
+      assert {:ok, [1, 2]} = Engine.call_fn(engine, Scope.new(), ast, "id", [{1, 2}])
+
+      scope = Scope.push(Scope.new(), "f", fn -> {:ok, 1} end)
+
+      assert {:ok, {:ok, 1}} =
+               Engine.eval_with_scope(engine, scope, "f.call()", tuples: :tagged)
+    end
+
+    test "should pass keyword lists as object maps" do
+      engine = Engine.new()
+      scope = Scope.push(Scope.new(), "opts", limit: 10, offset: 0)
+
+      assert {:ok, 10} = Engine.eval_with_scope(engine, scope, ~s|opts["limit"]|)
+      assert {:ok, true} = Engine.eval_with_scope(engine, scope, ~s|"offset" in opts|)
+      assert {:ok, "map"} = Engine.eval_with_scope(engine, scope, "type_of(opts)")
+      assert {:ok, [10, 0]} = Engine.eval_with_scope(engine, scope, "opts.values()")
+
+      script = "let sum = 0; for key in opts.keys() { sum += opts[key] } sum"
+
+      assert {:ok, 10} = Engine.eval_with_scope(engine, scope, script)
+    end
+
+    test "should return keyword lists with their keys in the same order" do
+      engine = Engine.new()
+
+      for tuples <- [:arrays, :tagged] do
+        scope = Scope.push(Scope.new(tuples: tuples), "opts", sort: "name", limit: 10, offset: 0)
+
+        assert {:ok, [sort: "name", limit: 10, offset: 0]} =
+                 Engine.eval_with_scope(engine, scope, "opts")
+
+        script = ~s|let o = opts; o.remove("sort"); o.limit *= 2; o.order = "asc"; o|
+
+        assert {:ok, [limit: 20, offset: 0, order: "asc"]} =
+                 Engine.eval_with_scope(engine, scope, script)
+      end
+    end
+
+    test "should return maps built from keyword lists as maps" do
+      scope = Scope.push(Scope.new(), "opts", limit: 10, offset: 0)
+
+      assert {:ok, %{"limit" => 10}} =
+               Engine.eval_with_scope(Engine.new(), scope, "opts.filter(|k, v| v > 0)")
+    end
+
+    test "should pass keyword lists with duplicate keys as lists" do
+      engine = Engine.new()
+      list = [ok: 1, error: 2, ok: 3]
+      scope = Scope.push(Scope.new(), "list", list)
+      tagged_scope = Scope.push(Scope.new(tuples: :tagged), "list", list)
+
+      assert {:ok, [["ok", 1], ["error", 2], ["ok", 3]]} =
+               Engine.eval_with_scope(engine, scope, "list")
+
+      assert {:ok, ^list} = Engine.eval_with_scope(engine, tagged_scope, "list")
+    end
+
+    test "should return an error for keyword list keys that are not existing atoms" do
+      scope = Scope.push(Scope.new(), "opts", limit: 10)
+
+      assert {:error, {:conversion, _}} =
+               Engine.eval_with_scope(Engine.new(), scope, "opts.not_an_atom_e8a7 = 1; opts")
+    end
+
+    test "should not convert empty lists and lists of blobs to keyword lists" do
+      scope = Scope.new() |> Scope.push("a", []) |> Scope.push("b", [{:blob, "x"}])
+
+      assert {:ok, [[], ["x"]]} = Engine.eval_with_scope(Engine.new(), scope, "[a, b]")
+    end
+  end
+
+  describe "eval/3 with pids, references and ports" do
+    test "should return pids, references and ports untouched" do
+      port = Port.open({:spawn, "cat"}, [])
+      ref = make_ref()
+      values = %{"pid" => self(), "ref" => ref, "port" => port}
+      scope = Scope.push(Scope.new(), "values", values)
+
+      assert {:ok, ^values} = Engine.eval_with_scope(Engine.new(), scope, "values")
+
+      assert {:ok, ["Pid", "Reference", "Port"]} =
+               Engine.eval_with_scope(Engine.new(), scope, """
+               [type_of(values.pid), type_of(values.ref), type_of(values.port)]
+               """)
+
+      Port.close(port)
+    end
+
+    test "should compare and print opaque values" do
+      scope = Scope.new() |> Scope.push("a", self()) |> Scope.push("b", self())
+      scope = Scope.push(scope, "c", spawn(fn -> :ok end))
+
+      assert {:ok, [true, false, false]} =
+               Engine.eval_with_scope(Engine.new(), scope, "[a == b, a == c, a == 1]")
+
+      assert {:ok, inspected} = Engine.eval_with_scope(Engine.new(), scope, "a.to_string()")
+      assert inspected =~ ~r/<\d+\.\d+\.\d+>/
+    end
+
+    test "should pass opaque values to Elixir functions" do
+      test_pid = self()
+      scope = Scope.push(Scope.new(), "reply", fn pid -> send(pid, :replied) end)
+      scope = Scope.push(scope, "from", test_pid)
+
+      assert {:ok, "replied"} = Engine.eval_with_scope(Engine.new(), scope, "reply.call(from)")
+      assert_receive :replied
+    end
+  end
+
+  describe "eval/3 with a timeout" do
+    test "should return a timeout error if the script runs past the deadline" do
+      assert {:error, {:timeout, "Script timed out after 50 ms"}} =
+               Engine.eval(Engine.new(), "loop {}", timeout: 50)
+    end
+
+    test "should not wait for a registered function past the deadline" do
+      pid = spawn_link(fn -> Process.sleep(:infinity) end)
+
+      assert {:error, {:timeout, _}} =
+               Engine.new()
+               |> Engine.register_fn("slow", 0, pid)
+               |> Engine.eval("slow()", timeout: 50)
+    end
+
+    test "should return the result if the script completes in time" do
+      assert {:ok, 2} = Engine.eval(Engine.new(), "1 + 1", timeout: 1_000)
+    end
+
+    test "should apply to run and call_fn variants" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn spin() { loop {} }")
+      {:ok, loop_ast} = Engine.compile(engine, "loop {}")
+
+      assert {:error, {:timeout, _}} =
+               Engine.run_ast_with_scope(engine, Scope.new(), loop_ast, timeout: 50)
+
+      assert {:error, {:timeout, _}} =
+               Engine.call_fn(engine, Scope.new(), ast, "spin", [], timeout: 50)
+    end
+  end
+
+  describe "eval/1 with thrown values" do
+    test "should return the thrown value with the runtime error" do
+      assert {:error, {:runtime, _, %{"code" => 42, "msg" => "invalid order"}}} =
+               Engine.eval(Engine.new(), ~s|throw \#{code: 42, msg: "invalid order"}|)
+    end
+
+    test "should return the thrown value from nested function calls" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, ~s|fn check(x) { if x < 0 { throw [x] } x } check(-1)|)
+
+      assert {:error, {:runtime, _, [-1]}} = Engine.eval_ast(engine, ast)
+    end
+
+    test "should return nil for values that cannot be converted" do
+      assert {:error, {:runtime, "Runtime error: 1..2" <> _, nil}} =
+               Engine.eval(Engine.new(), "throw 1..2")
+    end
+  end
+
+  describe "eval/1 with panics" do
+    test "should return a panic error and keep the engine usable" do
+      engine =
+        Engine.register_static_module!(
+          Engine.new(),
+          "plugin",
+          "#{File.cwd!()}/priv/native/libtest_dylib_module"
+        )
+
+      assert {:error, {:panic, message}} = Engine.eval(engine, "plugin::get_property(\#{})")
+      assert message =~ "None"
+
+      assert {:ok, 2} = Engine.eval(engine, "1 + 1")
+    end
+  end
+
+  describe "eval/1 with errors of no known type" do
+    test "should return an unknown error" do
+      assert {:error, {:unknown, _}} = Engine.eval(Engine.new(), "[1, 2].map(|x| exit(x))")
+    end
+  end
+
+  describe "eval/1 with values that cannot be converted" do
+    test "should return a conversion error with the path to the value" do
+      assert {:error, {:conversion, message}} =
+               Engine.new_raw()
+               |> Engine.set_module_resolvers([:dylib])
+               |> Engine.eval("""
+               import "#{File.cwd!()}/priv/native/libtest_dylib_module" as plugin;
+
+               \#{ objects: [plugin::new_plugin_object("inner")] }
+               """)
+
+      assert message =~ "Cannot convert a Rhai value of type"
+      assert message =~ ~s(at ["objects"][0])
+    end
+  end
+
   describe "eval_with_scope/3" do
     test "should eval a script with scope" do
       engine = Engine.new()
@@ -468,6 +1392,16 @@ defmodule Rhai.EngineTest do
 
       assert {:ok, 10} = Engine.call_fn(engine, scope, ast, "test", [3, 4])
     end
+
+    test "should return a conversion error if an argument cannot be converted" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn test(x, y) { x + y }")
+
+      assert {:error, {:conversion, message}} =
+               Engine.call_fn(engine, Scope.new(), ast, "test", [1, %{"a" => [[1 | 2]]}])
+
+      assert message =~ ~s(at args[1]["a"][0])
+    end
   end
 
   describe "set_fail_on_invalid_map_property/2, fail_on_invalid_map_property?/1" do
@@ -788,5 +1722,59 @@ defmodule Rhai.EngineTest do
                |> Engine.set_max_string_size(1)
                |> Engine.ensure_data_size_within_limits("[1, 2]")
     end
+
+    test "should return error if the value cannot be converted" do
+      assert {:error, {:conversion, _}} =
+               Engine.new()
+               |> Engine.ensure_data_size_within_limits(%{1 => 2})
+    end
+  end
+
+  describe "concurrency" do
+    test "should evaluate scripts on a shared engine and AST concurrently" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "fn double(x) { x * 2 }")
+
+      results =
+        1..20
+        |> Task.async_stream(fn i ->
+          {:ok, result} = Engine.call_fn(engine, Scope.new(), ast, "double", [i])
+          result
+        end)
+        |> Enum.map(fn {:ok, result} -> result end)
+
+      assert Enum.map(1..20, &(&1 * 2)) == results
+    end
+
+    test "should raise a busy error when configuring an engine while it is evaluating" do
+      engine = Engine.new()
+
+      task = Task.async(fn -> Engine.eval(engine, "loop {}", timeout: 200) end)
+      Process.sleep(50)
+
+      assert_raise ErlangError, ~r/busy/, fn -> Engine.set_max_operations(engine, 10) end
+      assert {:error, {:timeout, _}} = Task.await(task)
+      assert %Engine{} = Engine.set_max_operations(engine, 10)
+    end
+
+    test "should return a busy error when a scope is used by another evaluation" do
+      engine = Engine.new()
+      scope = Scope.new()
+
+      task = Task.async(fn -> Engine.eval_with_scope(engine, scope, "loop {}", timeout: 200) end)
+      Process.sleep(50)
+
+      assert {:error, {:busy, _}} = Engine.eval_with_scope(engine, scope, "1")
+      assert_raise RuntimeError, fn -> Scope.push(scope, "x", 1) end
+      assert {:error, {:timeout, _}} = Task.await(task)
+    end
+
+    test "should merge an AST with itself" do
+      engine = Engine.new()
+      {:ok, ast} = Engine.compile(engine, "40 + 2")
+
+      assert {:ok, 42} = Engine.eval_ast(engine, AST.merge(ast, ast))
+      assert {:ok, 42} = Engine.eval_ast(engine, AST.combine(ast, ast))
+    end
   end
 end
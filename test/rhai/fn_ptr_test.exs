@@ -0,0 +1,67 @@
+defmodule Rhai.FnPtrTest do
+  use ExUnit.Case
+
+  alias Rhai.{Engine, FnPtr, Scope}
+
+  describe "call/4" do
+    test "should call a closure returned by a script" do
+      engine = Engine.new()
+
+      assert {:ok, %FnPtr{} = validator} =
+               Engine.eval(engine, "let min = 3; |name| name.len() >= min")
+
+      assert {:ok, true} = FnPtr.call(validator, engine, ["rhai"])
+      assert {:ok, false} = FnPtr.call(validator, engine, ["ex"])
+    end
+
+    test "should call a pointer to a script-defined function" do
+      engine = Engine.new()
+
+      assert {:ok, %FnPtr{name: "double"} = double} =
+               Engine.eval(engine, ~s|fn double(x) { x * 2 } Fn("double")|)
+
+      assert {:ok, 42} = FnPtr.call(double, engine, [21])
+    end
+
+    test "should call a function pointer with curried arguments" do
+      engine = Engine.new()
+      {:ok, ast} =
+        Engine.compile(engine, ~s|fn add(a, b) { a + b } fn adder(a) { Fn("add").curry(a) }|)
+
+      assert {:ok, add_40} = Engine.call_fn(engine, Scope.new(), ast, "adder", [40])
+      assert {:ok, 42} = FnPtr.call(add_40, engine, [2])
+    end
+
+    test "should return function pointers nested in arrays and maps" do
+      engine = Engine.new()
+
+      assert {:ok, [%FnPtr{}, %{"f" => %FnPtr{} = f}]} =
+               Engine.eval(engine, "[|x| x, \#{f: |x| x + 1}]")
+
+      assert {:ok, 2} = FnPtr.call(f, engine, [1])
+    end
+
+    test "should pass function pointers back to scripts" do
+      engine = Engine.new()
+      script = "fn fold(list, f) { list.reduce(f, 0) } fn sum() { |acc, x| acc + x }"
+      {:ok, ast} = Engine.compile(engine, script)
+      {:ok, sum} = Engine.call_fn(engine, Scope.new(), ast, "sum", [])
+
+      assert {:ok, 6} = Engine.call_fn(engine, Scope.new(), ast, "fold", [[1, 2, 3], sum])
+    end
+
+    test "should return errors raised by the function" do
+      engine = Engine.new()
+      {:ok, fail} = Engine.eval(engine, ~s/|| throw "boom"/)
+
+      assert {:error, {:runtime, _, "boom"}} = FnPtr.call(fail, engine, [])
+    end
+
+    test "should accept evaluation options" do
+      engine = Engine.new()
+      {:ok, spin} = Engine.eval(engine, "|| loop {}")
+
+      assert {:error, {:timeout, _}} = FnPtr.call(spin, engine, [], timeout: 50)
+    end
+  end
+end
@@ -0,0 +1,159 @@
+defmodule Rhai.ProcessModuleResolverTest do
+  use ExUnit.Case
+
+  alias Rhai.{Engine, ProcessModuleResolver}
+
+  defp serve(modules) do
+    test_pid = self()
+
+    spawn_link(fn -> loop(test_pid, modules) end)
+  end
+
+  defp loop(test_pid, modules) do
+    receive do
+      {:rhai_import, callback, path} ->
+        send(test_pid, {:imported, path})
+
+        case Map.fetch(modules, path) do
+          {:ok, module} -> Rhai.Callback.reply(callback, {:ok, module})
+          :error -> Rhai.Callback.reply(callback, {:error, :not_found})
+        end
+
+        loop(test_pid, modules)
+    end
+  end
+
+  # Wait until `count` imports are pending before replying to them with the source of the module.
+  defp serve_when_pending(test_pid, count, source) do
+    callbacks =
+      for _ <- 1..count do
+        receive do
+          {:rhai_import, callback, _path} -> callback
+        end
+      end
+
+    Enum.each(callbacks, &Rhai.Callback.reply(&1, {:ok, source}))
+
+    loop(test_pid, %{"math" => source})
+  end
+
+  defp engine(resolver), do: Engine.set_module_resolvers(Engine.new(), [resolver])
+
+  describe "new/2" do
+    test "should import modules from the source replied by the process" do
+      pid = serve(%{"math" => "fn double(x) { x * 2 }"})
+      engine = engine(ProcessModuleResolver.new(pid))
+
+      assert {:ok, 42} = Engine.eval(engine, ~s|import "math" as m; m::double(21)|)
+      assert_receive {:imported, "math"}
+    end
+
+    test "should import modules from the AST replied by the process" do
+      {:ok, ast} = Engine.compile(Engine.new(), "export const ANSWER = 42;")
+      pid = serve(%{"answer" => ast})
+      engine = engine(ProcessModuleResolver.new(pid))
+
+      assert {:ok, 42} = Engine.eval(engine, ~s|import "answer" as a; a::ANSWER|)
+    end
+
+    test "should serve more concurrent imports than dirty CPU schedulers" do
+      test_pid = self()
+      schedulers = :erlang.system_info(:dirty_cpu_schedulers)
+      source = "fn double(x) { x * 2 }"
+      pid = spawn_link(fn -> serve_when_pending(test_pid, schedulers, source) end)
+      engine = engine(ProcessModuleResolver.new(pid, cache: false))
+
+      results =
+        1..(schedulers + 2)
+        |> Enum.map(fn i ->
+          Task.async(fn -> Engine.eval(engine, ~s|import "math" as m; m::double(#{i})|) end)
+        end)
+        |> Task.await_many(10_000)
+
+      assert results == Enum.map(1..(schedulers + 2), &{:ok, &1 * 2})
+    end
+
+    test "should cache the resolved modules" do
+      pid = serve(%{"math" => "fn double(x) { x * 2 }"})
+      engine = engine(ProcessModuleResolver.new(pid))
+
+      assert {:ok, 2} = Engine.eval(engine, ~s|import "math" as m; m::double(1)|)
+      assert {:ok, 4} = Engine.eval(engine, ~s|import "math" as m; m::double(2)|)
+
+      assert_receive {:imported, "math"}
+      refute_receive {:imported, "math"}
+    end
+
+    test "should ask the process on every import without cache" do
+      pid = serve(%{"math" => "fn double(x) { x * 2 }"})
+      engine = engine(ProcessModuleResolver.new(pid, cache: false))
+
+      assert {:ok, 2} = Engine.eval(engine, ~s|import "math" as m; m::double(1)|)
+      assert {:ok, 4} = Engine.eval(engine, ~s|import "math" as m; m::double(2)|)
+
+      assert_receive {:imported, "math"}
+      assert_receive {:imported, "math"}
+    end
+
+    test "should return a module not found error if the process replies with an error" do
+      engine = engine(ProcessModuleResolver.new(serve(%{})))
+
+      assert {:error, {:module_not_found, _}} = Engine.eval(engine, ~s|import "missing" as m;|)
+    end
+
+    test "should return a module not found error if the process does not reply in time" do
+      pid = spawn_link(fn -> Process.sleep(:infinity) end)
+      engine = engine(ProcessModuleResolver.new(pid, timeout: 10))
+
+      assert {:error, {:module_not_found, _}} = Engine.eval(engine, ~s|import "slow" as m;|)
+    end
+
+    test "should return an error if the module fails to compile" do
+      engine = engine(ProcessModuleResolver.new(serve(%{"broken" => "fn broken( {"})))
+
+      assert {:error, {:parsing, _}} = Engine.eval(engine, ~s|import "broken" as m;|)
+    end
+
+    test "should stop waiting for the process at the deadline of the evaluation" do
+      pid = spawn_link(fn -> Process.sleep(:infinity) end)
+      engine = engine(ProcessModuleResolver.new(pid))
+
+      assert {:error, {:timeout, _}} = Engine.eval(engine, ~s|import "slow" as m;|, timeout: 50)
+    end
+  end
+
+  describe "invalidate/2" do
+    test "should ask the process for the module again" do
+      pid = serve(%{"math" => "fn double(x) { x * 2 }"})
+      resolver = ProcessModuleResolver.new(pid)
+      engine = engine(resolver)
+
+      assert {:ok, 2} = Engine.eval(engine, ~s|import "math" as m; m::double(1)|)
+      assert_receive {:imported, "math"}
+
+      ProcessModuleResolver.invalidate(resolver, "math")
+
+      assert {:ok, 4} = Engine.eval(engine, ~s|import "math" as m; m::double(2)|)
+      assert_receive {:imported, "math"}
+    end
+  end
+
+  describe "invalidate_all/1" do
+    test "should ask the process for all the modules again" do
+      pid = serve(%{"a" => "export const VALUE = 1;", "b" => "export const VALUE = 2;"})
+      resolver = ProcessModuleResolver.new(pid)
+      engine = engine(resolver)
+      script = ~s|import "a" as a; import "b" as b; a::VALUE + b::VALUE|
+
+      assert {:ok, 3} = Engine.eval(engine, script)
+      assert_receive {:imported, "a"}
+      assert_receive {:imported, "b"}
+
+      ProcessModuleResolver.invalidate_all(resolver)
+
+      assert {:ok, 3} = Engine.eval(engine, script)
+      assert_receive {:imported, "a"}
+      assert_receive {:imported, "b"}
+    end
+  end
+end
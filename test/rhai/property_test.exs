@@ -49,6 +49,28 @@ defmodule Rhai.PropertyTest do
       end
     end
 
+    property "should convert tuple() to rhai Tuple type and vice-versa with tagged tuples" do
+      engine = Engine.new()
+
+      check all tuple <- tuple({integer(), string(:ascii), tuple({boolean()})}) do
+        scope = Scope.new(tuples: :tagged) |> Scope.push("a", tuple)
+        assert {:ok, ^tuple} = Engine.eval_with_scope(engine, scope, "a")
+      end
+    end
+
+    property "should convert keyword() to rhai object map type and vice-versa" do
+      engine = Engine.new()
+
+      check all keyword <-
+                  uniq_list_of({member_of([:a, :b, :c, :d]), integer()},
+                    uniq_fun: &elem(&1, 0),
+                    min_length: 1
+                  ) do
+        scope = Scope.new() |> Scope.push("a", keyword)
+        assert {:ok, ^keyword} = Engine.eval_with_scope(engine, scope, "a")
+      end
+    end
+
     property "should convert list() to rhai array type and vice-versa" do
       engine = Engine.new()
 
@@ -72,6 +94,41 @@ defmodule Rhai.PropertyTest do
       end
     end
 
+    property "should convert non UTF-8 binary() to rhai blob type and vice-versa" do
+      engine = Engine.new()
+
+      check all bin <- filter(binary(min_length: 1), &(not String.valid?(&1))) do
+        scope = Scope.new() |> Scope.push("a", bin)
+        assert {:ok, "blob"} = Engine.eval_with_scope(engine, scope, "type_of(a)")
+        assert {:ok, result} = Engine.eval_with_scope(engine, scope, "a")
+        assert bin == result
+      end
+    end
+
+    property "should convert {:blob, binary()} to rhai blob type and vice-versa" do
+      engine = Engine.new()
+
+      check all bin <- binary() do
+        scope = Scope.new() |> Scope.push("a", {:blob, bin})
+        assert {:ok, "blob"} = Engine.eval_with_scope(engine, scope, "type_of(a)")
+        assert {:ok, result} = Engine.eval_with_scope(engine, scope, "a")
+        assert bin == result
+      end
+    end
+
+    property "should convert DateTime.t() and NaiveDateTime.t() to rhai date-times and vice-versa" do
+      engine = Engine.new()
+
+      check all microseconds <- integer(-62_135_596_800_000_000..253_402_300_799_999_999) do
+        date_time = DateTime.from_unix!(microseconds, :microsecond)
+        naive_date_time = DateTime.to_naive(date_time)
+        scope = Scope.new() |> Scope.push("a", date_time) |> Scope.push("b", naive_date_time)
+
+        assert {:ok, ^date_time} = Engine.eval_with_scope(engine, scope, "a")
+        assert {:ok, ^naive_date_time} = Engine.eval_with_scope(engine, scope, "b")
+      end
+    end
+
     property "should convert Map.t() with String.t() keys to rhai object map type and vice-versa" do
       engine = Engine.new()
 
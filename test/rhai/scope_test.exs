@@ -23,6 +23,12 @@ defmodule ScopeTest do
       assert Scope.contains?(scope, "a")
       refute Scope.constant?(scope, "a")
     end
+
+    test "should raise if the value cannot be converted" do
+      assert_raise ArgumentError, ~r/does not fit into a Rhai \w+ at a\[1\]/, fn ->
+        Scope.new() |> Scope.push("a", [1, 1_267_650_600_228_229_401_496_703_205_376])
+      end
+    end
   end
 
   describe "push_constant/3" do
@@ -0,0 +1,110 @@
+defmodule Rhai.StaticModuleResolverTest do
+  use ExUnit.Case
+
+  alias Rhai.{Engine, StaticModuleResolver}
+
+  @greeter ~s|export const GREETING = "Hello"; fn greet(name) { `${global::GREETING}, ${name}` }|
+
+  describe "add/5" do
+    test "should resolve imports of the registered modules" do
+      engine = Engine.new()
+      resolver = StaticModuleResolver.new()
+      {:ok, resolver} = StaticModuleResolver.add(resolver, engine, "greeter", @greeter)
+      engine = Engine.set_module_resolvers(engine, [resolver])
+
+      assert {:ok, "Hello, Rhai"} =
+               Engine.eval(engine, ~s|import "greeter" as g; g::greet("Rhai")|)
+
+      assert {:ok, "Hello"} = Engine.eval(engine, ~s|import "greeter" as g; g::GREETING|)
+    end
+
+    test "should replace a module without setting the resolvers again" do
+      resolver = StaticModuleResolver.new()
+      engine = Engine.set_module_resolvers(Engine.new(), [resolver])
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "answer", "export const VALUE = 1;")
+
+      assert {:ok, 1} = Engine.eval(engine, ~s|import "answer" as a; a::VALUE|)
+
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "answer", "export const VALUE = 42;")
+
+      assert {:ok, 42} = Engine.eval(engine, ~s|import "answer" as a; a::VALUE|)
+    end
+
+    test "should import other modules of the resolver" do
+      resolver = StaticModuleResolver.new()
+      engine = Engine.set_module_resolvers(Engine.new(), [resolver])
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "greeter", @greeter)
+
+      {:ok, _} =
+        StaticModuleResolver.add(
+          resolver,
+          engine,
+          "welcome",
+          ~s|import "greeter" as g; fn welcome() { g::greet("world") }|
+        )
+
+      assert {:ok, "Hello, world"} =
+               Engine.eval(engine, ~s|import "welcome" as w; w::welcome()|)
+    end
+
+    test "should not register modules that fail to compile or evaluate" do
+      engine = Engine.new()
+      resolver = StaticModuleResolver.new()
+
+      assert {:error, {:parsing, _}} =
+               StaticModuleResolver.add(resolver, engine, "broken", "fn broken( {")
+
+      assert {:error, {:runtime, _, "boom"}} =
+               StaticModuleResolver.add(resolver, engine, "failing", ~s|throw "boom";|)
+
+      assert [] = StaticModuleResolver.paths(resolver)
+    end
+
+    test "should accept evaluation options" do
+      resolver = StaticModuleResolver.new()
+
+      assert {:error, {:timeout, _}} =
+               StaticModuleResolver.add(resolver, Engine.new(), "spin", "loop {}", timeout: 50)
+    end
+  end
+
+  describe "remove/2" do
+    test "should remove a module" do
+      resolver = StaticModuleResolver.new()
+      engine = Engine.set_module_resolvers(Engine.new(), [resolver])
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "greeter", @greeter)
+
+      assert StaticModuleResolver.contains_path?(resolver, "greeter")
+      assert %StaticModuleResolver{} = StaticModuleResolver.remove(resolver, "greeter")
+      refute StaticModuleResolver.contains_path?(resolver, "greeter")
+
+      assert {:error, {:module_not_found, _}} =
+               Engine.eval(engine, ~s|import "greeter" as g; g::greet("Rhai")|)
+    end
+  end
+
+  describe "paths/1" do
+    test "should return the sorted paths of the modules" do
+      engine = Engine.new()
+      resolver = StaticModuleResolver.new()
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "b", "")
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "a", "")
+
+      assert ["a", "b"] = StaticModuleResolver.paths(resolver)
+    end
+  end
+
+  describe "set_module_resolvers/2" do
+    test "should fall back to the next resolver" do
+      resolver = StaticModuleResolver.new()
+      engine = Engine.set_module_resolvers(Engine.new(), [resolver, :file])
+      {:ok, _} = StaticModuleResolver.add(resolver, engine, "greeter", @greeter)
+
+      assert {:ok, "Hello, Rhai"} =
+               Engine.eval(engine, ~s|import "greeter" as g; g::greet("Rhai")|)
+
+      assert {:ok, 43} =
+               Engine.eval(engine, ~s|import "#{File.cwd!()}/test/fixtures/script" as m; 43|)
+    end
+  end
+end
@@ -1 +1,4 @@
-ExUnit.start()
+features = String.split(System.get_env("RHAI_RUSTLER_FEATURES", ""), ",", trim: true)
+
+# Tests of optional cargo features only run when the NIF is built with them.
+ExUnit.start(exclude: for(feature <- [:decimal], to_string(feature) not in features, do: feature))